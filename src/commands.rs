@@ -1,3 +1,5 @@
+mod apply;
+mod config;
 mod dupes;
 mod join;
 mod list;
@@ -5,30 +7,54 @@ mod probe;
 mod rebuild;
 mod rename;
 
-use crate::entries::{EffectiveInput, Entry, InputInfo, TraversalMode};
+use crate::entries::{EffectiveInput, Entry, Input, InputInfo, TraversalMode};
 use crate::utils::natural_cmp;
 use anyhow::Result;
 use clap::Subcommand;
 
 #[derive(Debug, Subcommand)]
 pub enum Command {
+    /// Apply a plan saved by rename, rebuild, or join, validating it against the current files.
+    #[command(override_usage = "refine apply <FILE> [OPTIONS]")]
+    Apply(apply::Apply),
+    /// Show, validate, and edit the configuration file with named profiles.
+    #[command(override_usage = "refine config [OPTIONS]")]
+    Config(config::Config),
     /// Find possibly duplicated files by both size/sample and filename similarity.
-    #[command(override_usage = "refine dupes [DIRS]... [FETCH] [OPTIONS]")]
+    #[command(
+        override_usage = "refine dupes [DIRS]... [FETCH] [OPTIONS]",
+        args_override_self = true
+    )]
     Dupes(dupes::Dupes),
     /// Join files into a single directory with advanced conflict resolution.
-    #[command(override_usage = "refine join [DIRS]... [FETCH] [OPTIONS]")]
+    #[command(
+        override_usage = "refine join [DIRS]... [FETCH] [OPTIONS]",
+        args_override_self = true
+    )]
     Join(join::Join),
     /// List files from multiple disjoint directories sorted together.
-    #[command(override_usage = "refine list [DIRS]... [FETCH] [OPTIONS]")]
+    #[command(
+        override_usage = "refine list [DIRS]... [FETCH] [OPTIONS]",
+        args_override_self = true
+    )]
     List(list::List),
     /// Rebuild entire media collections' filenames intelligently.
-    #[command(override_usage = "refine rebuild [DIRS]... [FETCH] [OPTIONS]")]
+    #[command(
+        override_usage = "refine rebuild [DIRS]... [FETCH] [OPTIONS]",
+        args_override_self = true
+    )]
     Rebuild(rebuild::Rebuild),
     /// Rename files and directories in batch using advanced regex rules.
-    #[command(override_usage = "refine rename [DIRS]... [FETCH] [OPTIONS]")]
+    #[command(
+        override_usage = "refine rename [DIRS]... [FETCH] [OPTIONS]",
+        args_override_self = true
+    )]
     Rename(rename::Rename),
     /// Probe collections' filenames against a remote server.
-    #[command(override_usage = "refine probe [DIRS]... [FETCH] [OPTIONS]")]
+    #[command(
+        override_usage = "refine probe [DIRS]... [FETCH] [OPTIONS]",
+        args_override_self = true
+    )]
     Probe(probe::Probe),
 }
 
@@ -48,18 +74,33 @@ pub trait Refine {
     fn refine(&self, medias: Vec<Self::Media>) -> Result<()>;
 }
 
-// /// The common interface for commands that change the configuration of Refine commands.
-// pub trait Configure {
-//     /// The opening line to display when running the command.
-//     const OPENING_LINE: &'static str;
-//
-//     /// Actual command implementation.
-//     fn config(&self) -> Result<()>;
-// }
-// fn configure<C: Configure>(mut r: C, fetcher: Fetcher, info: InputSpec) -> Result<()> {
-//     println!("=> {}\n", C::OPENING_LINE);
-//     r.config()
-// }
+/// The common interface for commands that change the configuration of Refine commands.
+pub trait Configure {
+    /// The opening line to display when running the command.
+    const OPENING_LINE: &'static str;
+
+    /// Actual command implementation, called with the selected profile, if any.
+    fn config(&self, profile: Option<&str>) -> Result<()>;
+}
+
+/// The common interface for commands that replay saved plans, instead of fetching entries.
+pub trait Replay {
+    /// The opening line to display when running the command.
+    const OPENING_LINE: &'static str;
+
+    /// Actual command implementation, which loads its own input.
+    fn replay(&self) -> Result<()>;
+}
+
+fn configure<C: Configure>(opt: C, ei: EffectiveInput) -> Result<()> {
+    println!("=> {}\n", C::OPENING_LINE);
+    opt.config(ei.profile.as_deref())
+}
+
+fn replay<R: Replay>(opt: R) -> Result<()> {
+    println!("=> {}\n", R::OPENING_LINE);
+    opt.replay()
+}
 
 fn refine<R: Refine>(mut opt: R, ei: EffectiveInput) -> Result<()> {
     println!("=> {}\n", R::OPENING_LINE);
@@ -79,7 +120,13 @@ fn show<R: Refine>(_: R, ei: EffectiveInput) {
 }
 
 impl Command {
-    pub fn execute(self, ei: EffectiveInput) -> Result<()> {
+    pub fn execute(self, input: Input) -> Result<()> {
+        match self {
+            Command::Apply(_) => input.reject_fetch("apply")?,
+            Command::Config(_) => input.reject_fetch("config")?,
+            _ => {}
+        }
+        let ei: EffectiveInput = input.try_into()?;
         macro_rules! call {
             ($opt:expr) => {
                 match ei.show {
@@ -89,6 +136,8 @@ impl Command {
             };
         }
         match self {
+            Command::Apply(opt) => replay(opt),
+            Command::Config(opt) => configure(opt, ei),
             Command::Dupes(opt) => call!(opt),
             Command::Join(opt) => call!(opt),
             Command::List(opt) => call!(opt),
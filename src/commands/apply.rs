@@ -0,0 +1,68 @@
+use crate::commands::Replay;
+use crate::medias::{Plan, ReadyOp, Transfer};
+use crate::utils;
+use anyhow::{Result, anyhow};
+use clap::Args;
+use std::path::PathBuf;
+
+#[derive(Debug, Args)]
+pub struct Apply {
+    /// The plan file to apply.
+    #[arg(value_name = "FILE")]
+    file: PathBuf,
+    #[command(flatten)]
+    transfer: Transfer,
+    /// Skip the stale operations, instead of refusing the whole plan.
+    #[arg(short = 's', long)]
+    skip_stale: bool,
+    /// Skip the confirmation prompt, useful for automation.
+    #[arg(short = 'y', long)]
+    yes: bool,
+}
+
+impl Replay for Apply {
+    const OPENING_LINE: &'static str = "Apply a saved plan";
+
+    fn replay(&self) -> Result<()> {
+        let plan = Plan::load(&self.file)?;
+        println!("plan: {} operations from {}", plan.ops.len(), plan.command);
+
+        // step: validate the plan against the current state of the file system.
+        let (mut ops, stale) = plan.validate();
+        if !stale.is_empty() {
+            println!();
+            stale
+                .iter()
+                .for_each(|(op, reason)| eprintln!("stale: {reason}: {op}"));
+            if !self.skip_stale {
+                return Err(anyhow!(
+                    "found {} stale operations, nothing was changed (use --skip-stale)",
+                    stale.len()
+                ));
+            }
+        }
+
+        // step: display a summary receipt.
+        println!("\ntotal operations: {}", ops.len() + stale.len());
+        println!("  ready: {}", ops.len());
+        println!("  stale: {}", stale.len());
+        if ops.is_empty() {
+            return Ok(());
+        }
+
+        // step: apply changes if the user agrees.
+        if !self.yes {
+            utils::prompt_yes_no("apply changes?")?;
+        }
+        ops = ReadyOp::apply_all(ops, &self.transfer);
+
+        match ops.is_empty() {
+            _ if !utils::is_running() => {
+                println!("aborted, {} remaining; run again to resume", ops.len())
+            }
+            true => println!("done"),
+            false => println!("found {} errors", ops.len()),
+        }
+        Ok(())
+    }
+}
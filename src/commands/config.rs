@@ -0,0 +1,123 @@
+use crate::commands::Configure;
+use crate::config::Profiles;
+use crate::utils::{self, PromptError};
+use anyhow::{Context, Result, anyhow};
+use clap::Args;
+use std::fs;
+
+#[derive(Debug, Args)]
+pub struct Config {
+    /// Validate the configuration file against the available options.
+    #[arg(short = 'c', long)]
+    check: bool,
+    /// Open the configuration file in $EDITOR, creating it if needed and validating it afterwards.
+    #[arg(short = 'e', long, conflicts_with = "check")]
+    edit: bool,
+}
+
+const TEMPLATE: &str = r#"# refine configuration file.
+#
+# Each table is a named profile, used like `refine rebuild --profile movies`.
+# A profile holds default options in sections: `filter` for all commands, `naming` for the
+# commands with naming rules, `scheme` for the ones that read collection names, and any command
+# name like `probe` or `dupes` for its own options.
+# The keys are the long option names, and the ones given in the command line take precedence,
+# while repeatable options like naming rules are appended. Booleans set here can be turned off in
+# the command line with `--no-<flag>`, like `--no-only-files`.
+#
+# [movies.naming]
+# strip-after = ["720p", "1080p"]
+# replace = ["-+=-"]
+#
+# [movies.filter]
+# ext-in = "mp4|mkv"
+#
+# [movies.scheme]
+# seq-sep = " - "
+# seq-width = 3
+#
+# [movies.probe]
+# url = "https://example.com/$/"
+# backoff = 2.0
+"#;
+
+impl Configure for Config {
+    const OPENING_LINE: &'static str = "Configure profiles";
+
+    fn config(&self, profile: Option<&str>) -> Result<()> {
+        let path = Profiles::path()?;
+        println!("config file: {}", path.display());
+        if self.edit {
+            return edit();
+        }
+
+        let Some(profiles) = Profiles::load()? else {
+            println!("\nno configuration file found, create one with `refine config --edit`");
+            return Ok(());
+        };
+        if self.check {
+            return check(&profiles);
+        }
+
+        // step: display the options each profile provides.
+        let names = match profile {
+            Some(p) => {
+                profiles.sections(p)?; // make sure it exists.
+                vec![p]
+            }
+            None => profiles.names().collect(),
+        };
+        names.iter().try_for_each(|&p| {
+            println!("\n{p}:");
+            profiles
+                .sections(p)?
+                .keys()
+                .for_each(|s| match profiles.section_args(p, s) {
+                    Ok(args) => println!("  {s}: {}", args.join(" ")),
+                    Err(err) => println!("  {s}: error: {err:#}"),
+                });
+            Ok::<_, anyhow::Error>(())
+        })?;
+
+        // step: display a summary receipt.
+        println!("\ntotal profiles: {}", names.len());
+        Ok(())
+    }
+}
+
+fn check(profiles: &Profiles) -> Result<()> {
+    let errors = profiles.validate();
+    errors.iter().for_each(|err| eprintln!("error: {err:#}"));
+    match errors.len() {
+        0 => {
+            println!("\nconfig ok: {} profiles", profiles.names().count());
+            Ok(())
+        }
+        n => Err(anyhow!("found {n} errors")),
+    }
+}
+
+fn edit() -> Result<()> {
+    let path = Profiles::path()?;
+    if !path.exists() {
+        fs::create_dir_all(path.parent().unwrap())
+            .and_then(|()| fs::write(&path, TEMPLATE))
+            .with_context(|| format!("creating {path:?}"))?;
+    }
+    loop {
+        utils::launch_editor(&path)?;
+        let res = Profiles::load().and_then(|p| match p {
+            Some(profiles) => check(&profiles),
+            None => Err(anyhow!("the configuration file was removed")),
+        });
+        match res {
+            Ok(()) => return Ok(()),
+            Err(err) => eprintln!("error: {err:#}"),
+        }
+        match utils::prompt_yes_no("edit again?") {
+            Ok(()) => {}
+            Err(PromptError::No) => return Err(anyhow!("invalid configuration file")),
+            Err(err) => return Err(err.into()),
+        }
+    }
+}
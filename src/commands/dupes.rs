@@ -1,18 +1,16 @@
 use crate::commands::Refine;
 use crate::entries::{Entry, InputInfo, TraversalMode};
+use crate::medias::{SAMPLE_SIZE, file_sample, media_kind};
 use crate::utils::{self, display_abort};
 use anyhow::Result;
 use clap::{Args, ValueEnum};
 use deunicode::deunicode;
 use human_repr::HumanCount;
-use mime_guess::MimeGuess;
 use rayon::prelude::*;
 use regex::Regex;
 use std::boxed::Box;
 use std::cmp::{Ordering, Reverse};
 use std::collections::{HashMap, HashSet};
-use std::fs::File;
-use std::io::{self, Read, Seek, SeekFrom};
 use std::sync::atomic::{AtomicUsize, Ordering as AtomicOrdering};
 use std::sync::{Arc, LazyLock, Mutex};
 use std::time::{Duration, Instant};
@@ -27,7 +25,7 @@ pub struct Dupes {
     #[arg(short = 'm', long, default_value_t = SearchMode::All, value_name = "STR", value_enum)]
     mode: SearchMode,
     /// Sample size in kbytes (0 to disable).
-    #[arg(short = 's', long, default_value_t = 4, value_name = "INT")]
+    #[arg(short = 's', long, default_value_t = SAMPLE_SIZE / 1024, value_name = "INT")]
     sample: usize,
     /// The threshold for similarity checks (0.0 to 1.0).
     #[arg(short = 't', long, default_value_t = 0.7, value_name = "FLOAT")]
@@ -271,11 +269,10 @@ impl Dupes {
                 let clean1 = &medias[a].cleaned_name;
                 let clean2 = &medias[b].cleaned_name;
                 let sim = {
-                    let lev = strsim::normalized_levenshtein(clean1, clean2);
-                    let dice = strsim::sorensen_dice(clean1, clean2);
+                    let string_sim = utils::similarity(clean1, clean2);
                     let rare_token_boost = rare_token_similarity(clean1, clean2, &token_freq);
                     // combine all three metrics: 40% string similarity, 60% rare token similarity.
-                    (lev.max(dice) * 0.4) + (rare_token_boost * 0.6)
+                    (string_sim * 0.4) + (rare_token_boost * 0.6)
                 };
                 (sim >= self.threshold).then_some((a, b, sim))
             })
@@ -459,38 +456,7 @@ fn rare_token_similarity(a: &str, b: &str, token_freq: &HashMap<&str, usize>) ->
 impl Media {
     fn cache_sample(&mut self, size: usize) {
         if self.sample.is_none() {
-            let grab_sample = || {
-                let mut file = File::open(&self.entry)?;
-                let file_len = self.size;
-
-                if file_len <= size as u64 {
-                    // read the whole file if it's smaller than the sample size.
-                    let mut buf = Vec::with_capacity(file_len as usize);
-                    file.read_to_end(&mut buf)?;
-                    return Ok::<_, io::Error>(buf);
-                }
-
-                // allocate buffer for all chunks.
-                let mut buf = vec![0; size];
-                let chunk_size = size / 3; // may not be divisible by 3, but that's okay.
-
-                // read from the start.
-                file.read_exact(&mut buf[..chunk_size])?;
-
-                // read from the middle.
-                let mid_pos = file_len / 2 - chunk_size as u64 / 2;
-                file.seek(SeekFrom::Start(mid_pos))?;
-                file.read_exact(&mut buf[chunk_size..chunk_size * 2])?;
-
-                // read from the end; this last chunk must compensate for the remainder of division.
-                let end_pos = file_len - (size - chunk_size * 2) as u64;
-                file.seek(SeekFrom::Start(end_pos))?;
-                file.read_exact(&mut buf[chunk_size * 2..])?;
-
-                Ok(buf)
-            };
-
-            self.sample = match grab_sample() {
+            self.sample = match file_sample(&self.entry, self.size, size) {
                 Ok(buf) => Some(Some(buf.into_boxed_slice())),
                 Err(err) => {
                     eprintln!("error: load sample: {err:?}.");
@@ -556,34 +522,6 @@ fn clean_words(name: &str) -> String {
     }
 }
 
-fn classify_media_kind(ext: &str) -> &'static str {
-    let ext = ext.to_ascii_lowercase();
-    let ext = ext.as_str();
-    // guess the mime type from the extension.
-    let mime = MimeGuess::from_ext(ext).first_raw().unwrap_or_default();
-    let top = mime.split('/').next().unwrap_or_default();
-
-    match top {
-        "video" | "audio" | "image" | "text" => top,
-        "application" => match ext {
-            // video extensions that are misclassified as application.
-            "mkv" | "webm" | "rmvb" | "m2ts" | "mts" | "f4v" | "vob" | "ogv" => "video",
-            // document.
-            "pdf" | "doc" | "docx" | "xls" | "xlsx" | "ppt" | "pptx" | "odt" | "ods" | "odp"
-            | "rtf" => "document",
-            // archive.
-            "zip" | "rar" | "7z" | "tar" | "gz" | "bz2" | "xz" | "lz" | "lzma" | "iso" | "cab"
-            | "arj" | "z" => "archive",
-            // subtitle.
-            "srt" | "ass" | "ssa" | "sub" | "vtt" | "idx" | "sup" => "subtitle",
-            // text (some application/* are actually text).
-            "csv" | "json" | "xml" | "yaml" | "yml" | "ini" | "conf" => "text",
-            _ => "application",
-        },
-        _ => "unknown",
-    }
-}
-
 impl TryFrom<Entry> for Media {
     type Error = (Entry, anyhow::Error);
 
@@ -592,7 +530,7 @@ impl TryFrom<Entry> for Media {
         Ok(Media {
             size: entry.metadata().map_or(0, |m| m.len()),
             cleaned_name: clean_words(stem),
-            kind: classify_media_kind(ext),
+            kind: media_kind(ext),
             entry,
             sample: None,
         })
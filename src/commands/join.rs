@@ -1,18 +1,27 @@
 use crate::commands::Refine;
-use crate::entries::{Entry, Fetcher, ROOT, Recurse, TraversalMode};
+use crate::entries::{Entry, Fetcher, InputInfo, ROOT, Recurse, TraversalMode};
 use crate::impl_source_entry;
-use crate::medias::{FileOps, NewEntry, SourceEntry};
-use crate::utils;
+use crate::medias::{
+    FileOps, NewEntry, NewNameMut, OpKind, Plan, Sidecars, SourceEntry, Template, Transfer,
+    fold_key, is_case_insensitive, review, same_content, temp_name,
+};
+use crate::utils::{self, PromptError};
 use anyhow::{Context, Result, anyhow};
+use clap::builder::NonEmptyStringValueParser;
 use clap::{Args, ValueEnum};
-use std::collections::HashSet;
+use std::collections::{BTreeSet, HashSet};
 use std::fmt::Display;
 use std::fs;
 use std::path::PathBuf;
 use std::sync::OnceLock;
+use std::time::SystemTime;
 
 #[derive(Debug, Args)]
 pub struct Join {
+    #[command(flatten)]
+    sidecars: Sidecars,
+    #[command(flatten)]
+    transfer: Transfer,
     /// The target directory; will be created if it doesn't exist.
     #[arg(short = 't', long, default_value = ".", value_name = "PATH")]
     target: PathBuf,
@@ -25,9 +34,31 @@ pub struct Join {
     /// Force joining already in place files and directories, i.e. in subdirectories of the target.
     #[arg(short = 'f', long)]
     force: bool,
+    /// Organize files into date folders like "2024/06", by EXIF date, a date in the name, or mtime;
+    /// a shorthand for `--into "{date:%Y}/{date:%m}"`.
+    #[arg(short = 'o', long)]
+    organize: bool,
+    /// Keep the directory structure relative to the input paths, instead of flattening it.
+    #[arg(short = 'k', long, conflicts_with = "organize")]
+    keep_tree: bool,
+    /// Sort files into folders built from a template, like "{kind}", "{ext}", or "{mtime:%Y}/{1}".
+    #[arg(long, value_name = "TEMPLATE", conflicts_with_all = ["organize", "keep_tree"], value_parser = NonEmptyStringValueParser::new())]
+    into: Option<String>,
+    /// Only sort files whose names match this into folders; its groups become placeholders.
+    #[arg(long, value_name = "REGEX", requires = "into", allow_hyphen_values = true, value_parser = NonEmptyStringValueParser::new())]
+    into_match: Option<String>,
     /// Do not remove empty parent directories after joining files.
     #[arg(short = 'p', long)]
     parents: bool,
+    /// Treat names differing only by case as clashes (auto-detected on case-insensitive file systems).
+    #[arg(long)]
+    fold_case: bool,
+    /// Review each change interactively, to accept, skip, or edit it before applying.
+    #[arg(long, conflicts_with = "yes")]
+    review: bool,
+    /// Save the changes to a plan file instead of applying them, to run later with `refine apply`.
+    #[arg(long, value_name = "FILE")]
+    save_plan: Option<PathBuf>,
     /// Skip the confirmation prompt, useful for automation.
     #[arg(short = 'y', long)]
     yes: bool,
@@ -39,6 +70,15 @@ pub enum By {
     Move,
     #[value(aliases = ["c", "cp"])]
     Copy,
+    /// Clone files with copy-on-write where supported, like Btrfs, XFS, or APFS, or copy them.
+    #[value(aliases = ["r", "cow"])]
+    Reflink,
+    /// Hard link files within the same file system, recreating directories.
+    #[value(aliases = ["h", "hard"])]
+    Hardlink,
+    /// Create symbolic links to the files and directories.
+    #[value(aliases = ["s", "sym"])]
+    Symlink,
 }
 
 #[derive(Debug, Clone, Copy, ValueEnum)]
@@ -51,12 +91,27 @@ pub enum Clashes {
     NameParent,
     #[value(aliases = ["i", "ig"])]
     Ignore,
+    /// Drop the identical copies, removing them when moving, and add sequence numbers to the others.
+    #[value(aliases = ["d", "dd"])]
+    Dedup,
+    /// Keep only the most recently modified file, replacing the one in the target if needed.
+    #[value(aliases = ["n", "nw"])]
+    Newer,
+    /// Keep only the largest file, replacing the one in the target if needed.
+    #[value(aliases = ["l", "lg"])]
+    Larger,
+    /// Skip the identical copies, and add sequence numbers to the others.
+    #[value(aliases = ["kb", "kbd"])]
+    KeepBothIfDifferent,
 }
 
 #[derive(Debug)]
 pub struct Media {
     entry: Entry,
     new_name: Option<String>,
+    /// The folder inside the target, i.e. the relative path when keeping the tree, or the rendered
+    /// template when sorting into folders, like the date when organizing.
+    dir: Option<String>,
     skip: Skip,
 }
 
@@ -65,6 +120,11 @@ enum Skip {
     Yes,
     No,
     Target,
+    Identical,
+    /// An identical copy that will be removed.
+    Remove,
+    /// A file in the target that will be replaced.
+    Replace,
 }
 
 #[derive(Debug)]
@@ -74,12 +134,24 @@ struct Shared {
 }
 
 static SHARED: OnceLock<Shared> = OnceLock::new();
+static ROOTS: OnceLock<Vec<Entry>> = OnceLock::new();
+
+/// The folders template of `--organize`.
+const ORGANIZE: &str = "{date:%Y}/{date:%m}";
 
 impl Refine for Join {
     type Media = Media;
     const OPENING_LINE: &'static str = "Join files";
     const T_MODE: TraversalMode = TraversalMode::DirsStop;
 
+    fn tweak(&mut self, info: &InputInfo) {
+        ROOTS.set(info.dirs.clone()).unwrap();
+
+        if self.organize {
+            self.into = Some(ORGANIZE.to_owned());
+        }
+    }
+
     fn refine(&self, mut medias: Vec<Self::Media>) -> Result<()> {
         if self.target.is_file() {
             return Err(anyhow!("invalid target: must be a directory or not exist"));
@@ -91,82 +163,130 @@ impl Refine for Join {
             force: self.force,
         };
         SHARED.set(shared).unwrap();
+        let into = self
+            .into
+            .as_deref()
+            .map(|t| Template::parse_dirs(t, self.into_match.as_deref()))
+            .transpose()?;
+
+        // step: find the folders of the files when keeping the tree or sorting into folders,
+        // expanding the directories.
+        if self.keep_tree || into.is_some() {
+            medias = medias
+                .into_iter()
+                .flat_map(|m| match m.entry.is_dir() {
+                    true => Fetcher::single(&m.entry, Recurse::Full)
+                        .fetch(TraversalMode::Files)
+                        .map(Media::new)
+                        .collect(),
+                    false => vec![m],
+                })
+                .collect();
+        }
+        if self.keep_tree {
+            let roots = ROOTS.get().unwrap().iter().map(Entry::resolve);
+            let roots = roots.collect::<Result<Vec<_>>>()?;
+            medias
+                .iter_mut()
+                .for_each(|m| m.dir = tree_dir(&m.entry, &roots));
+        }
         let total = medias.len();
+        let mut blocked = 0;
+        if let Some(into) = &into {
+            medias.retain_mut(|m| match into.folder(&m.entry) {
+                None => true,
+                Some(Ok(dir)) => {
+                    m.dir = (!dir.is_empty()).then_some(dir);
+                    true
+                }
+                Some(Err(err)) => {
+                    eprintln!("blocked: {err}: {}", m.entry);
+                    blocked += 1;
+                    false
+                }
+            });
+        }
+        let (bundles, _) = self.sidecars.detach(&mut medias)?;
 
-        // step: read the target directory, which might not be empty, to detect outer clashes (not in medias).
+        // step: read the target directories, which might not be empty, to detect outer clashes (not in medias).
         let mut target_names = Vec::new();
-        if target.exists() {
+        let dirs = medias
+            .iter()
+            .map(|m| m.dir.clone())
+            .collect::<BTreeSet<_>>();
+        for dir in dirs {
+            let path = dir.as_ref().map_or(target.clone(), |d| target.join(d));
+            if !path.exists() {
+                continue;
+            }
             // if target happens to be inside any input path and is not empty, this will dup the files.
-            let fetcher = Fetcher::single(&target, Recurse::Shallow);
-            let in_target = fetcher.fetch(Join::T_MODE).collect::<Vec<_>>();
-            target_names.extend(in_target.iter().map(|e| e.file_name().to_string()));
-            medias.extend(in_target.into_iter().map(|entry| Media {
-                entry,
-                new_name: None,
+            let fetcher = Fetcher::single(&path, Recurse::Shallow);
+            let in_target = fetcher.fetch(Join::T_MODE).map(|entry| Media {
+                dir: dir.clone(),
                 skip: Skip::Target,
-            }));
+                ..Media::new(entry)
+            });
+            let in_target = in_target.collect::<Vec<_>>();
+            target_names.extend(in_target.iter().map(Media::rel_name));
+            medias.extend(in_target);
         }
 
+        // step: detect case-insensitive target file systems.
+        let fold = self.fold_case || {
+            let fold = is_case_insensitive(&target);
+            if fold {
+                eprintln!("Enabling fold-case mode due to case-insensitive file system.\n");
+            }
+            fold
+        };
+        target_names
+            .iter_mut()
+            .for_each(|t| *t = fold_key(t, fold).into_owned());
+
         // step: detect clashes (files with the same name in different directories), and resolve them.
-        medias.sort_unstable_by(|m, n| {
-            // put files already in place first.
-            (m.entry.file_name(), !m.is_in_place()).cmp(&(n.entry.file_name(), !n.is_in_place()))
-        });
-        medias.dedup_by(|m, n| m.entry.to_str() == n.entry.to_str()); // remove target dup files.
-        let mut clashes = 0;
-        medias
-            .chunk_by_mut(|m, n| m.entry.file_name() == n.entry.file_name())
-            .filter(|g| g.len() > 1)
-            .for_each(|g| {
-                clashes += g.len() - 1; // one is (or will be) in target, the others are clashes.
-                let (stem, ext) = g[0].entry.filename_parts();
-                let (stem, ext) = (stem.to_owned(), ext.to_owned()); // g must not be borrowed.
-                let dot = if ext.is_empty() { "" } else { "." };
-                match self.clashes {
-                    Clashes::NameSequence => {
-                        let mut seq = 2..;
-                        g.iter_mut().skip(1).for_each(|m| {
-                            let new_name = (&mut seq)
-                                .map(|i| format!("{stem}-{i}{dot}{ext}"))
-                                .find(|s| target_names.iter().all(|t| s != t))
-                                .unwrap();
-                            m.new_name = Some(new_name);
-                        });
-                    }
-                    Clashes::ParentName | Clashes::NameParent => g.iter_mut().for_each(|m| {
-                        let par = m.entry.parent().unwrap_or(ROOT.clone());
-                        let par = par.file_name();
-                        if let Clashes::ParentName = self.clashes {
-                            m.new_name = Some(format!("{par}-{stem}{dot}{ext}"));
-                        } else {
-                            m.new_name = Some(format!("{stem}-{par}{dot}{ext}"));
-                        }
-                    }),
-                    Clashes::Ignore => g.iter_mut().for_each(|m| m.skip = Skip::Yes),
-                }
-            });
+        let clashes = self.resolve_clashes(&mut medias, &target_names, fold);
 
         // step: settle results by removing the files that are in place or skipped.
-        medias.sort_unstable_by(|m, n| m.entry.cmp(&n.entry));
-        let mut in_place = 0;
-        medias.retain(|m| match (m.skip, m.is_in_place()) {
-            (Skip::No, false) => true,
-            (Skip::No, true) => {
-                in_place += 1;
-                println!("already in place: {}", m.entry);
-                false
+        let (in_place, mut removals, replaced) = settle(&mut medias, fold);
+
+        // step: review the changes before the sidecars are attached, so they follow the decisions.
+        if self.save_plan.is_some() && !(removals.is_empty() && replaced.is_empty()) {
+            return Err(anyhow!(
+                "plans can't remove files, use another clash strategy"
+            ));
+        }
+        if self.review {
+            if !medias.is_empty() {
+                review(&mut medias, fold)?;
             }
-            (Skip::Yes, _) => {
-                println!("clash skipped: {}", m.entry);
-                false
+            if !removals.is_empty() {
+                // the removals are not changes of names, so they are confirmed separately.
+                println!();
+                removals.iter().for_each(|e| println!("to remove: {e}"));
+                let msg = format!("remove these {} identical copies?", removals.len());
+                match utils::prompt_yes_no(msg) {
+                    Ok(()) => {}
+                    Err(PromptError::No) => removals.clear(),
+                    Err(err) => return Err(err.into()),
+                }
             }
-            (Skip::Target, _) => false,
+        }
+
+        // step: move the sidecars along with their primaries.
+        blocked += bundles.attach(&mut medias, |m, entry, name| Media {
+            new_name: Some(name),
+            dir: m.dir.clone(),
+            ..Media::new(entry)
         });
 
         // step: display the results.
-        medias.iter().for_each(|m| match &m.new_name {
-            Some(name) => println!("{} -> {name}", m.entry),
-            None => println!("{}", m.entry),
+        medias.iter().for_each(|m| {
+            let dir = m.dir.as_ref().map(|d| format!("{d}/")).unwrap_or_default();
+            match &m.new_name {
+                Some(name) => println!("{} -> {dir}{name}", m.entry),
+                None if m.dir.is_some() => println!("{} -> {dir}", m.entry),
+                None => println!("{}", m.entry),
+            }
         });
 
         // step: display summary receipt.
@@ -177,40 +297,58 @@ impl Refine for Join {
         let resolved: &dyn Display = if clashes > 0 { &self.clashes } else { &"" };
         println!("  clashes: {clashes}{resolved}");
         println!("  in place: {in_place}");
-        println!("\njoin [by {:?}] to: {target}", self.by);
+        if blocked > 0 {
+            println!("  blocked: {blocked}");
+        }
+        if !removals.is_empty() {
+            println!("  to remove: {}", removals.len());
+        }
+        if !replaced.is_empty() {
+            println!("  to replace: {}", replaced.len());
+        }
+        let organized = match (self.keep_tree, &self.into) {
+            (true, _) => " (keeping the tree)".to_owned(),
+            (_, Some(into)) => format!(" (sorted into {into:?})"),
+            _ => String::new(),
+        };
+        println!("\njoin [by {:?}] to: {target}{organized}", self.by);
 
         // step: ask for confirmation.
-        if medias.is_empty() {
+        if medias.is_empty() && removals.is_empty() {
             println!("nothing to do");
             return Ok(());
         }
-        if !self.yes {
+        if !self.review && !self.yes && self.save_plan.is_none() {
             utils::prompt_yes_no("apply changes?")?;
         }
+        if let Some(path) = &self.save_plan {
+            return Plan::save(
+                path,
+                "join",
+                match self.by {
+                    By::Move => OpKind::Move,
+                    By::Copy => OpKind::Copy,
+                    By::Reflink => OpKind::Reflink,
+                    By::Hardlink => OpKind::Hardlink,
+                    By::Symlink => OpKind::Symlink,
+                },
+                &medias,
+            );
+        }
 
         // step: grab the files' parent directories before the consuming operations.
         let dirs = match self.parents {
             true => HashSet::new(),
             false => medias
                 .iter()
-                .map(|m| m.entry.parent().unwrap())
+                .map(|m| &m.entry)
+                .chain(&removals)
+                .map(|e| e.parent().unwrap())
                 .collect::<HashSet<_>>(),
         };
 
         // step: apply changes if the user agrees.
-        fs::create_dir_all(&target).with_context(|| format!("creating {target:?}"))?;
-        match self.by {
-            By::Move => FileOps::rename_move(&mut medias),
-            By::Copy => FileOps::copy(&mut medias),
-        };
-
-        // step: recover from CrossDevice errors.
-        if !medias.is_empty()
-            && let By::Move = self.by
-        {
-            println!("attempting to fix {} errors", medias.len());
-            FileOps::cross_move(&mut medias);
-        }
+        self.apply(&mut medias, removals, &replaced, fold, &target)?;
 
         // step: remove the empty parent directories.
         if !self.parents {
@@ -225,26 +363,283 @@ impl Refine for Join {
                         .is_some_and(|v| !v.is_empty()) // an empty iterator is collected into Some([]).
                     {
                         let dstore = dir.join(DS_STORE);
-                        if let Err(err) = fs::remove_file(&dstore) {
+                        if let Err(err) = self.transfer.trash.remove(&dstore) {
                             eprintln!("error: {err}: {dstore:?}");
                         }
                     }
                 }
-                if let Ok(()) = fs::remove_dir(&dir) {
-                    println!("  removed empty dir: {dir}")
+                let is_empty = fs::read_dir(&dir).is_ok_and(|mut rd| rd.next().is_none());
+                if is_empty {
+                    match self.transfer.trash.remove(&dir) {
+                        Ok(()) => println!("  removed empty dir: {dir}"),
+                        Err(err) => eprintln!("error: {err}: {dir}"),
+                    }
                 }
             });
         }
 
         match (medias.is_empty(), self.by) {
+            _ if !utils::is_running() => {
+                println!("aborted, {} remaining; run again to resume", medias.len())
+            }
             (true, _) => println!("done"),
-            (false, By::Move) => println!("still {} errors, giving up", medias.len()),
-            (false, By::Copy) => println!("found {} errors", medias.len()),
+            (false, By::Move | By::Reflink) => {
+                println!("still {} errors, giving up", medias.len())
+            }
+            (false, _) => println!("found {} errors", medias.len()),
+        }
+        Ok(())
+    }
+}
+
+impl Join {
+    /// Find the clashes, i.e. files that would get the same name in the target, including the ones
+    /// already there, and resolve them with the clash strategy; returns how many were found.
+    fn resolve_clashes(
+        &self,
+        medias: &mut Vec<Media>,
+        target_names: &[String],
+        fold: bool,
+    ) -> usize {
+        let key = |m: &Media| fold_key(&m.rel_name(), fold).into_owned();
+        medias.sort_by_cached_key(|m| {
+            // put files already in place first.
+            (key(m), !m.is_in_place(), m.entry.to_str().to_owned())
+        });
+        medias.dedup_by(|m, n| {
+            // remove target dup files; the ones that can't be resolved are never dups.
+            matches!((m.entry.resolve(), n.entry.resolve()), (Ok(p), Ok(q)) if p == q)
+        });
+        let mut clashes = 0;
+        medias
+            .chunk_by_mut(|m, n| key(m) == key(n))
+            .filter(|g| g.len() > 1)
+            .for_each(|g| {
+                clashes += g.len() - 1; // one is (or will be) in target, the others are clashes.
+                if self.keep_tree && !self.clashes.by_content() {
+                    // merging trees often finds the same files, which don't need to be resolved.
+                    let mut kept = vec![0]; // the first one is (or will be) in target.
+                    for i in 1..g.len() {
+                        match kept.iter().any(|&k| same_content(&g[k].entry, &g[i].entry)) {
+                            true => g[i].skip = Skip::Identical,
+                            false => kept.push(i),
+                        }
+                    }
+                }
+                let (stem, ext) = g[0].entry.filename_parts();
+                let (stem, ext) = (stem.to_owned(), ext.to_owned()); // g must not be borrowed.
+                let dot = if ext.is_empty() { "" } else { "." };
+                let dir = g[0]
+                    .dir
+                    .as_ref()
+                    .map(|d| format!("{d}/"))
+                    .unwrap_or_default();
+                let mut seq = 2..;
+                let mut next_name = || {
+                    (&mut seq)
+                        .map(|i| format!("{stem}-{i}{dot}{ext}"))
+                        .find(|s| {
+                            let s = format!("{dir}{s}");
+                            target_names.iter().all(|t| fold_key(&s, fold) != *t)
+                        })
+                        .unwrap()
+                };
+                match self.clashes {
+                    Clashes::NameSequence => g
+                        .iter_mut()
+                        .skip(1)
+                        .filter(|m| m.is_pending())
+                        .for_each(|m| m.new_name = Some(next_name())),
+                    Clashes::ParentName | Clashes::NameParent => {
+                        g.iter_mut().filter(|m| m.is_pending()).for_each(|m| {
+                            let par = m.entry.parent().unwrap_or(ROOT.clone());
+                            let par = par.file_name();
+                            if let Clashes::ParentName = self.clashes {
+                                m.new_name = Some(format!("{par}-{stem}{dot}{ext}"));
+                            } else {
+                                m.new_name = Some(format!("{stem}-{par}{dot}{ext}"));
+                            }
+                        })
+                    }
+                    Clashes::Ignore => g
+                        .iter_mut()
+                        .filter(|m| m.is_pending())
+                        .for_each(|m| m.skip = Skip::Yes),
+                    Clashes::Dedup | Clashes::KeepBothIfDifferent => {
+                        let identical = match (self.clashes, self.by) {
+                            (Clashes::Dedup, By::Move) => Skip::Remove,
+                            _ => Skip::Identical,
+                        };
+                        let mut kept = vec![0]; // the first one is (or will be) in target.
+                        for i in 1..g.len() {
+                            if !g[i].is_pending() {
+                                continue;
+                            }
+                            if kept.iter().any(|&k| same_content(&g[k].entry, &g[i].entry)) {
+                                g[i].skip = identical;
+                            } else {
+                                g[i].new_name = Some(next_name());
+                                kept.push(i);
+                            }
+                        }
+                    }
+                    Clashes::Newer | Clashes::Larger => {
+                        let rank = |m: &Media| {
+                            let md = m.entry.metadata().ok()?;
+                            match self.clashes {
+                                Clashes::Newer => md.modified().ok().map(Rank::Time),
+                                _ => Some(Rank::Size(md.len())),
+                            }
+                        };
+                        let winner = (0..g.len())
+                            .filter(|&i| i == 0 || g[i].is_pending())
+                            .rev() // the first one wins ties.
+                            .max_by_key(|&i| rank(&g[i]))
+                            .unwrap();
+                        for (i, m) in g.iter_mut().enumerate() {
+                            match (i, m.is_pending()) {
+                                _ if i == winner => {}
+                                (0, false) => m.skip = Skip::Replace,
+                                (0, true) if m.is_in_place() => m.skip = Skip::Replace,
+                                (_, true) => m.skip = Skip::Yes,
+                                _ => {}
+                            }
+                        }
+                    }
+                }
+            });
+        clashes
+    }
+
+    /// Remove the identical copies and apply the changes, moving the files that will be replaced
+    /// aside, and removing them only when their replacements succeed.
+    fn apply(
+        &self,
+        medias: &mut Vec<Media>,
+        removals: Vec<Entry>,
+        replaced: &HashSet<String>,
+        fold: bool,
+        target: &Entry,
+    ) -> Result<()> {
+        // step: remove the identical copies.
+        removals.into_iter().for_each(|entry| {
+            if let Err(err) = self.transfer.trash.remove(&entry) {
+                eprintln!("error: {err}: {entry}");
+            }
+        });
+
+        // step: move the files that will be replaced aside, so they are only removed after their
+        // replacements succeed; if this fails, the target still exists and the operation fails.
+        let aside = medias
+            .iter()
+            .map(|m| m.new_entry())
+            .filter(|e| replaced.contains(&*fold_key(e.to_str(), fold)))
+            .filter_map(|target| {
+                let temp = temp_name(&target);
+                match fs::rename(&target, &temp) {
+                    Ok(()) => Some((target, temp)),
+                    Err(err) => {
+                        eprintln!("error: {err}: {target} -> {temp}");
+                        None
+                    }
+                }
+            })
+            .collect::<Vec<_>>();
+
+        // step: join the files.
+        fs::create_dir_all(target).with_context(|| format!("creating {target:?}"))?;
+        medias
+            .iter()
+            .filter_map(|m| m.dir.as_ref())
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .try_for_each(|d| {
+                let path = target.join(d);
+                fs::create_dir_all(&path).with_context(|| format!("creating {path:?}"))
+            })?;
+        match self.by {
+            By::Move => FileOps::rename_move(medias),
+            By::Copy => FileOps::copy(medias, &self.transfer),
+            By::Reflink => FileOps::reflink(medias, &self.transfer),
+            By::Hardlink => FileOps::hard_link(medias),
+            By::Symlink => FileOps::symlink(medias),
+        };
+
+        // step: recover from CrossDevice errors.
+        if !medias.is_empty() && utils::is_running() && matches!(self.by, By::Move) {
+            println!("attempting to fix {} errors", medias.len());
+            FileOps::cross_move(medias, &self.transfer);
         }
+
+        // step: remove the replaced files, or restore them if their replacements failed.
+        let failed = medias.iter().map(|m| m.new_entry()).collect::<HashSet<_>>();
+        aside.into_iter().for_each(|(target, temp)| {
+            let res = match failed.contains(&target) {
+                true => fs::rename(&temp, &target),
+                false => self.transfer.trash.remove_as(&temp, &target),
+            };
+            if let Err(err) = res {
+                eprintln!("error: {err}: {temp} -> {target}");
+            }
+        });
         Ok(())
     }
 }
 
+/// Settle the results by removing the files that are in place or skipped, returning how many are
+/// in place, the identical copies to remove, and the target files to replace.
+fn settle(medias: &mut Vec<Media>, fold: bool) -> (usize, Vec<Entry>, HashSet<String>) {
+    medias.sort_unstable_by(|m, n| m.entry.cmp(&n.entry));
+    let (mut in_place, mut removals, mut replaced) = (0, vec![], HashSet::new());
+    medias.retain(|m| match (m.skip, m.is_in_place()) {
+        (Skip::No, false) => true,
+        (Skip::No, true) => {
+            in_place += 1;
+            println!("already in place: {}", m.entry);
+            false
+        }
+        (Skip::Yes, _) => {
+            println!("clash skipped: {}", m.entry);
+            false
+        }
+        (Skip::Identical, _) => {
+            println!("identical skipped: {}", m.entry);
+            false
+        }
+        (Skip::Remove, _) => {
+            println!("identical to remove: {}", m.entry);
+            removals.push(m.entry.clone());
+            false
+        }
+        (Skip::Replace, _) => {
+            println!("to replace: {}", m.entry);
+            if let Ok(entry) = m.entry.resolve() {
+                replaced.insert(fold_key(entry.to_str(), fold).into_owned());
+            }
+            false
+        }
+        (Skip::Target, _) => false,
+    });
+    (in_place, removals, replaced)
+}
+
+/// What is compared to choose the file to keep.
+#[derive(PartialEq, Eq, PartialOrd, Ord)]
+enum Rank {
+    Time(SystemTime),
+    Size(u64),
+}
+
+impl Clashes {
+    /// Whether the strategy compares the files, instead of just their names.
+    fn by_content(self) -> bool {
+        matches!(
+            self,
+            Clashes::Dedup | Clashes::Newer | Clashes::Larger | Clashes::KeepBothIfDifferent
+        )
+    }
+}
+
 impl Display for Clashes {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
@@ -252,44 +647,302 @@ impl Display for Clashes {
             Clashes::ParentName => write!(f, " (resolved by parent-name)"),
             Clashes::NameParent => write!(f, " (resolved by name-parent)"),
             Clashes::Ignore => write!(f, " (ignored)"),
+            Clashes::Dedup => write!(f, " (resolved by dedup)"),
+            Clashes::Newer => write!(f, " (resolved by newer)"),
+            Clashes::Larger => write!(f, " (resolved by larger)"),
+            Clashes::KeepBothIfDifferent => write!(f, " (resolved by keep-both-if-different)"),
         }
     }
 }
 
 impl Media {
+    fn new(entry: Entry) -> Self {
+        Media {
+            new_name: None,
+            dir: None,
+            skip: Skip::No,
+            entry,
+        }
+    }
+
+    /// The name of the file relative to the target, which includes its date folder when organizing.
+    fn rel_name(&self) -> String {
+        match &self.dir {
+            Some(dir) => format!("{dir}/{}", self.entry.file_name()),
+            None => self.entry.file_name().to_owned(),
+        }
+    }
+
+    fn is_pending(&self) -> bool {
+        matches!(self.skip, Skip::No)
+    }
+
     fn is_in_place(&self) -> bool {
         let shared = SHARED.get().unwrap();
 
         let target = &shared.target;
+        let Ok(entry) = self.entry.resolve() else {
+            return false;
+        }; // the target is resolved, so the entry must be too.
+        if let Some(dir) = &self.dir {
+            return entry.parent().unwrap() == target.join(dir);
+        }
         if shared.force {
-            return self.entry.parent().unwrap() == *target;
+            return entry.parent().unwrap() == *target;
         }
 
-        match self.entry.is_dir() {
-            true => self.entry.starts_with(target),
-            false => self.entry.parent().unwrap().starts_with(target),
+        match entry.is_dir() {
+            true => entry.starts_with(target),
+            false => entry.parent().unwrap().starts_with(target),
         }
     }
 }
 
+/// The folder of a file relative to its input path, i.e. the deepest one that contains it.
+fn tree_dir(entry: &Entry, roots: &[Entry]) -> Option<String> {
+    let parent = entry.resolve().ok()?.parent()?;
+    let root = roots
+        .iter()
+        .filter(|r| parent.starts_with(r))
+        .max_by_key(|r| r.as_os_str().len())?;
+    let rel = parent.strip_prefix(root).ok()?.to_str()?;
+    (!rel.is_empty()).then(|| rel.to_owned())
+}
+
 impl_source_entry!(Media);
 
 impl NewEntry for Media {
     fn new_entry(&self) -> Entry {
         let name = self.new_name.as_ref().map(|s| s.as_ref());
         let path = &SHARED.get().unwrap().target;
+        let path = self.dir.as_ref().map_or(path.clone(), |d| path.join(d));
         path.join(name.unwrap_or_else(|| self.src_entry().file_name()))
     }
 }
 
+impl NewNameMut for Media {
+    fn new_name_mut(&mut self) -> &mut String {
+        self.new_name
+            .get_or_insert_with(|| self.entry.file_name().to_owned())
+    }
+}
+
 impl TryFrom<Entry> for Media {
     type Error = (Entry, anyhow::Error);
 
     fn try_from(entry: Entry) -> Result<Self, Self::Error> {
-        Ok(Media {
-            new_name: None,
-            skip: Skip::No,
-            entry,
-        })
+        Ok(Media::new(entry))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::Scratch;
+    use clap::{Command, FromArgMatches};
+
+    /// Parse the options from a command line.
+    fn join(args: &[&str]) -> Join {
+        let cmd = Join::augment_args(Command::new("join"));
+        let matches = cmd.get_matches_from(std::iter::once(&"join").chain(args));
+        Join::from_arg_matches(&matches).unwrap()
+    }
+
+    /// Create the medias of the files, which go into the "target" folder of the scratch directory;
+    /// the target is shared by all tests, so the folder is relative to the temp dir.
+    fn medias(dir: &Scratch) -> (Vec<Media>, Vec<String>) {
+        let temp = std::env::temp_dir().canonicalize().unwrap();
+        let target = Entry::try_new(&temp, true).unwrap();
+        SHARED.get_or_init(|| Shared {
+            target,
+            force: false,
+        });
+        let folder = dir.join("target");
+        let folder = folder.strip_prefix(&temp).unwrap().to_str().unwrap();
+        let medias = dir
+            .files()
+            .iter()
+            .map(|f| Media {
+                dir: Some(folder.to_owned()),
+                skip: match f.starts_with("target/") {
+                    true => Skip::Target,
+                    false => Skip::No,
+                },
+                ..Media::new(dir.entry(f))
+            })
+            .collect::<Vec<_>>();
+        let target_names = medias
+            .iter()
+            .filter(|m| matches!(m.skip, Skip::Target))
+            .map(Media::rel_name)
+            .collect();
+        (medias, target_names)
+    }
+
+    #[test]
+    fn keep_tree_identical() {
+        let dir = Scratch::new("join-tree");
+        dir.file("target/a.txt", "target")
+            .file("r1/a.txt", "new")
+            .file("r2/a.txt", "new")
+            .file("r3/a.txt", "target");
+        let (mut medias, target_names) = medias(&dir);
+
+        let clashes = join(&["-k"]).resolve_clashes(&mut medias, &target_names, false);
+        assert_eq!(clashes, 3);
+        let names = medias
+            .iter()
+            .map(|m| {
+                let src = m.entry.strip_prefix(&*dir).unwrap().to_str().unwrap();
+                let skip = matches!(m.skip, Skip::Identical);
+                (src, m.new_name.as_deref(), skip)
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            names,
+            [
+                ("target/a.txt", None, false),
+                ("r1/a.txt", Some("a-2.txt"), false),
+                ("r2/a.txt", None, true), // identical to the kept copy of r1.
+                ("r3/a.txt", None, true), // identical to the one in target.
+            ]
+        );
+    }
+
+    #[test]
+    fn clash_strategies() {
+        #[track_caller]
+        fn case(args: &[&str], files: &[(&str, &str, u64)], out: &[(&str, &str)]) {
+            let dir = Scratch::new("join-clashes");
+            for &(name, contents, mtime) in files {
+                dir.file(name, contents);
+                let file = fs::File::options()
+                    .write(true)
+                    .open(dir.join(name))
+                    .unwrap();
+                file.set_modified(SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(mtime))
+                    .unwrap();
+            }
+            let trash = dir.join("trash");
+            let mut args = args.to_vec();
+            if !args.contains(&"--permanent") {
+                args.extend(["--trash-dir", trash.to_str().unwrap()]);
+            }
+            let join = join(&args);
+            let (mut medias, target_names) = medias(&dir);
+
+            join.resolve_clashes(&mut medias, &target_names, false);
+            let (_, removals, replaced) = settle(&mut medias, false);
+            let target = SHARED.get().unwrap().target.clone();
+            join.apply(&mut medias, removals, &replaced, false, &target)
+                .unwrap();
+            assert!(medias.is_empty(), "failed: {medias:?}");
+            let files = dir
+                .files()
+                .into_iter()
+                .filter(|f| !f.starts_with("trash/info/"))
+                .map(|f| (fs::read_to_string(dir.join(&f)).unwrap(), f))
+                .collect::<Vec<_>>();
+            let files = files
+                .iter()
+                .map(|(contents, f)| (f.as_str(), contents.as_str()))
+                .collect::<Vec<_>>();
+            assert_eq!(files, out);
+        }
+
+        let same = [
+            ("target/a.txt", "same", 10),
+            ("r1/a.txt", "same", 20),
+            ("r2/a.txt", "diff", 30),
+        ];
+        // dedup removes the identical copies when moving, and skips them otherwise.
+        case(
+            &["-c", "dedup"],
+            &same,
+            &[
+                ("target/a-2.txt", "diff"),
+                ("target/a.txt", "same"),
+                ("trash/files/a.txt", "same"),
+            ],
+        );
+        case(
+            &["-c", "dedup", "-b", "copy"],
+            &same,
+            &[
+                ("r1/a.txt", "same"),
+                ("r2/a.txt", "diff"),
+                ("target/a-2.txt", "diff"),
+                ("target/a.txt", "same"),
+            ],
+        );
+        case(
+            &["-c", "dedup", "--permanent"],
+            &same,
+            &[("target/a-2.txt", "diff"), ("target/a.txt", "same")],
+        );
+        // keep-both-if-different never removes anything.
+        case(
+            &["-c", "kb"],
+            &same,
+            &[
+                ("r1/a.txt", "same"),
+                ("target/a-2.txt", "diff"),
+                ("target/a.txt", "same"),
+            ],
+        );
+
+        // newer and larger replace the target, which goes to the trash, and skip the others.
+        let files = [
+            ("target/a.txt", "old", 10),
+            ("r1/a.txt", "newest", 30),
+            ("r2/a.txt", "newer", 20),
+        ];
+        case(
+            &["-c", "newer"],
+            &files,
+            &[
+                ("r2/a.txt", "newer"),
+                ("target/a.txt", "newest"),
+                ("trash/files/a.txt", "old"),
+            ],
+        );
+        case(
+            &["-c", "larger", "-b", "copy"],
+            &files,
+            &[
+                ("r1/a.txt", "newest"),
+                ("r2/a.txt", "newer"),
+                ("target/a.txt", "newest"),
+                ("trash/files/a.txt", "old"),
+            ],
+        );
+        case(
+            &["-c", "newer", "--permanent"],
+            &files,
+            &[("r2/a.txt", "newer"), ("target/a.txt", "newest")],
+        );
+        // the target wins, so nothing is replaced.
+        let files = [("target/a.txt", "largest", 30), ("r1/a.txt", "large", 20)];
+        case(
+            &["-c", "newer"],
+            &files,
+            &[("r1/a.txt", "large"), ("target/a.txt", "largest")],
+        );
+        case(
+            &["-c", "larger"],
+            &files,
+            &[("r1/a.txt", "large"), ("target/a.txt", "largest")],
+        );
+        // without a target, the winner is moved and the loser stays.
+        case(
+            &["-c", "larger"],
+            &[("r1/a.txt", "large", 10), ("r2/a.txt", "larger", 20)],
+            &[("r1/a.txt", "large"), ("target/a.txt", "larger")],
+        );
+        case(
+            &["-c", "newer"],
+            &[("r1/a.txt", "old", 10), ("r2/a.txt", "new", 20)],
+            &[("r1/a.txt", "old"), ("target/a.txt", "new")],
+        );
     }
 }
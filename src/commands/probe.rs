@@ -1,5 +1,5 @@
 use crate::commands::Refine;
-use crate::entries::{Entry, InputInfo, TraversalMode};
+use crate::entries::{Entry, InputInfo, Scheme, TraversalMode};
 use crate::utils::{self, display_abort};
 use Verdict::*;
 use anyhow::{Context, Result, anyhow};
@@ -37,6 +37,8 @@ pub struct Probe {
     /// Specify when to display errors.
     #[arg(short = 'e', long, default_value_t = Errors::Each10, value_name = "STR", value_enum)]
     errors: Errors,
+    #[command(flatten)]
+    scheme: Scheme,
     // /// The HTTP request method to use.
     // #[arg(short = 'm', long, default_value = "HEAD", value_name = "STR")]
     // method: Method,
@@ -80,6 +82,7 @@ impl Refine for Probe {
     const T_MODE: TraversalMode = TraversalMode::Files;
 
     fn tweak(&mut self, _: &InputInfo) {
+        self.scheme.activate();
         if self.retries < 0 && self.errors == Errors::Last {
             eprintln!("Displaying \"last\" error won't show anything with indefinite retries.\n");
             self.errors = Errors::Never;
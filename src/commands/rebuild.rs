@@ -1,14 +1,23 @@
 use crate::commands::Refine;
-use crate::entries::{Entry, InputInfo, TraversalMode};
-use crate::medias::{FileOps, Naming};
-use crate::utils::{self, PromptError};
+use crate::entries::{Entry, InputInfo, Scheme, TraversalMode};
+use crate::medias::{
+    FileOps, Naming, NamingRules, OpKind, Plan, Sidecars, name_date, parents_case_insensitive,
+    review,
+};
+use crate::utils::{self, PromptError, natural_cmp};
 use crate::{impl_new_name, impl_new_name_mut, impl_source_entry};
-use anyhow::Result;
-use clap::Args;
+use anyhow::{Context, Result, anyhow};
+use chrono::NaiveDateTime;
 use clap::builder::NonEmptyStringValueParser;
+use clap::{Args, ValueEnum};
 use regex::Regex;
 use std::borrow::Cow;
+use std::cell::RefCell;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fmt::{self, Display};
 use std::fs;
+use std::path::PathBuf;
+use std::str::FromStr;
 use std::sync::{LazyLock, OnceLock};
 use std::time::SystemTime;
 
@@ -16,21 +25,110 @@ use std::time::SystemTime;
 pub struct Rebuild {
     #[command(flatten)]
     naming: Naming,
+    #[command(flatten)]
+    scheme: Scheme,
+    #[command(flatten)]
+    sidecars: Sidecars,
     /// Disable smart matching, so "foo bar.mp4", "FooBar.mp4" and "foo__bar.mp4" are different.
     #[arg(short = 's', long)]
     simple: bool,
     /// Force to overwrite filenames (use the Global options to filter files).
-    #[arg(short = 'f', long, value_name = "STR", conflicts_with_all = ["strip_before", "strip_after", "strip_exact", "replace", "throw", "simple", "partial"], value_parser = NonEmptyStringValueParser::new())]
+    #[arg(short = 'f', long, value_name = "STR", conflicts_with_all = ["strip_before", "strip_after", "strip_exact", "replace", "throw", "casing", "rules", "simple", "partial"], value_parser = NonEmptyStringValueParser::new())]
     force: Option<String>,
     /// Assume not all directories are available, which retains current sequences (but fixes gaps).
     #[arg(short = 'p', long)]
     partial: bool,
-    /// Keep the original case of filenames, otherwise they are lowercased.
+    /// Where sequences are scoped: "global", "parent" dir, or the Nth ancestor dir, e.g. "depth=2".
+    #[arg(long, default_value_t = Scope::Global, value_name = "STR")]
+    scope: Scope,
+    /// Use the name of the scope directory as the base name (implies --scope parent if global).
+    #[arg(short = 'd', long, conflicts_with = "force")]
+    dir_name: bool,
+    /// How to order the files within each group, i.e. which gets the first sequence numbers.
+    #[arg(short = 'o', long, default_value_t = OrderBy::Created, value_name = "STR", value_enum)]
+    order_by: OrderBy,
+    /// Keep the original case of filenames, otherwise they are lowercased (unless casing recipes are given).
     #[arg(short = 'c', long)]
     case: bool,
+    /// Merge near-identical names like "foo-bar" and "f00bar" into the most common one, after confirmation.
+    #[arg(short = 'z', long, conflicts_with = "force")]
+    fuzzy: bool,
+    /// The threshold for fuzzy matching (0.0 to 1.0).
+    #[arg(
+        short = 't',
+        long,
+        default_value_t = 0.8,
+        value_name = "FLOAT",
+        requires = "fuzzy"
+    )]
+    threshold: f64,
+    /// Treat names differing only by case as the same (auto-detected on case-insensitive file systems).
+    #[arg(long)]
+    fold_case: bool,
+    /// Map a name to a canonical one, keeping the old name as its alias instead of any previous one, e.g. "beatles=the_beatles".
+    #[arg(long, value_name = "STR=STR", value_parser = utils::parse_key_value::<String, String>)]
+    alias: Vec<(String, String)>,
+    /// Load alias mappings from a file, one "old=new" per line, applied before the others.
+    #[arg(long, value_name = "FILE")]
+    alias_file: Vec<PathBuf>,
+    /// Report all canonical names with their aliases and counts.
+    #[arg(long)]
+    alias_report: bool,
+    /// Set the comment of all files, e.g. "best".
+    #[arg(long, value_name = "STR", conflicts_with = "comment_clear", help_heading = Some("Comments"))]
+    comment_set: Option<String>,
+    /// Clear the comment of all files.
+    #[arg(long, help_heading = Some("Comments"))]
+    comment_clear: bool,
+    /// Replace occurrences in comments, after setting them.
+    #[arg(long, value_name = "REGEX=STR|$N", allow_hyphen_values = true, value_parser = utils::parse_key_value::<String, String>, help_heading = Some("Comments"))]
+    comment_replace: Vec<(String, String)>,
+    /// Report the distinct comments of each collection, with their counts.
+    #[arg(long, help_heading = Some("Comments"))]
+    comment_report: bool,
+    /// Only check the collections for issues, without renaming anything (fails if any is found).
+    #[arg(long, conflicts_with_all = ["force", "yes"])]
+    check: bool,
+    /// Review each change interactively, to accept, skip, or edit it before applying.
+    #[arg(long, conflicts_with = "yes")]
+    review: bool,
+    /// Save the changes to a plan file instead of applying them, to run later with `refine apply`.
+    #[arg(long, value_name = "FILE")]
+    save_plan: Option<PathBuf>,
     /// Skip the confirmation prompt, useful for automation.
     #[arg(short = 'y', long)]
     yes: bool,
+    /// The naming rules, compiled once when tweaking, since their casing recipes change how the
+    /// medias are loaded.
+    #[arg(skip)]
+    rules: RefCell<Option<Box<Result<NamingRules>>>>,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum OrderBy {
+    /// The creation time, which some file systems lose when copying.
+    #[value(aliases = ["c"])]
+    Created,
+    /// The modification time.
+    #[value(aliases = ["m"])]
+    Modified,
+    /// The date embedded in the original name (e.g. 20240601_1530), then the creation time.
+    #[value(aliases = ["d", "date"])]
+    NameDate,
+    /// The original names, with numbers compared by value.
+    #[value(aliases = ["n"])]
+    Natural,
+}
+
+/// The scope of collections, i.e. which files share the same sequence.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Scope {
+    /// All files from all input directories.
+    Global,
+    /// The files in each directory.
+    Parent,
+    /// The files under each Nth ancestor directory, where 1 is the same as parent.
+    Depth(usize),
 }
 
 #[derive(Debug)]
@@ -39,8 +137,12 @@ pub struct Media {
     entry: Entry,
     /// The new generated filename.
     new_name: String,
+    /// The directory that scopes the sequence, if not global.
+    scope: Option<Entry>,
     /// The resulting smart match (if enabled and new_name has spaces or _).
     group_name: Option<String>,
+    /// The source alias, which is kept in the new name.
+    alias: Option<String>,
     /// The sequence number, which will be kept in partial mode and disambiguate `created` in all modes.
     seq: Option<usize>,
     /// A comment for the file.
@@ -49,6 +151,10 @@ pub struct Media {
     ext: &'static str,
     /// The creation time of the file.
     created: SystemTime,
+    /// The modification time of the file.
+    modified: SystemTime,
+    /// The date embedded in the original name, if any.
+    name_date: Option<NaiveDateTime>,
 }
 
 static CASE_FN: OnceLock<fn(&str) -> String> = OnceLock::new();
@@ -59,12 +165,29 @@ impl Refine for Rebuild {
     const T_MODE: TraversalMode = TraversalMode::Files;
 
     fn tweak(&mut self, info: &InputInfo) {
-        let f = match self.case {
+        self.scheme.activate();
+        // casing recipes need the original case; invalid rules are reported in refine.
+        let rules = self.naming.compile();
+        let casing = rules.as_ref().is_ok_and(NamingRules::has_casing);
+        *self.rules.get_mut() = Some(Box::new(rules));
+        let f = match self.case || casing {
             false => str::to_lowercase,
             true => str::to_owned,
         };
         CASE_FN.set(f).unwrap();
 
+        if self.threshold < 0.0 || self.threshold > 1.0 {
+            self.threshold = self.threshold.clamp(0.0, 1.0);
+            eprintln!(
+                "warning: invalid similarity threshold, using {:.1}",
+                self.threshold
+            );
+        }
+
+        if self.dir_name && self.scope == Scope::Global {
+            self.scope = Scope::Parent;
+        }
+
         if info.has_invalid && !self.partial && self.force.is_none() {
             self.partial = true;
             eprintln!("Enabling partial mode due to missing directories.\n");
@@ -73,6 +196,10 @@ impl Refine for Rebuild {
 
     fn refine(&self, mut medias: Vec<Self::Media>) -> Result<()> {
         let total_files = medias.len();
+        let (bundles, _) = self.sidecars.detach(&mut medias)?;
+        if self.check {
+            return self.check(&medias);
+        }
 
         // detect if migration is needed.
         static RE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^(\w+)-(\d+)$").unwrap());
@@ -81,7 +208,8 @@ impl Refine for Rebuild {
             .any(|m| m.seq.is_none() && RE.is_match(m.entry.filename_parts().0))
         {
             eprintln!("warning: detected old-style filenames.");
-            match utils::prompt_yes_no(r#"migrate to new style "name~9"?"#) {
+            let style = self.scheme.format("name", None, 9, "", "");
+            match utils::prompt_yes_no(format!("migrate to new style {style:?}?")) {
                 Ok(()) => {
                     medias.iter_mut().for_each(|m| {
                         if let Some(caps) = RE.captures(m.entry.filename_parts().0) {
@@ -99,8 +227,34 @@ impl Refine for Rebuild {
             }
         }
 
+        // step: find the scope directories, and use their names if requested.
+        if let Some(depth) = self.scope.depth() {
+            medias.iter_mut().for_each(|m| {
+                m.scope = ancestor(&m.entry, depth);
+            });
+        }
+        if self.dir_name {
+            for m in &mut medias {
+                let Some(dir) = &m.scope else { continue }; // dir_name always implies a scope.
+                let name = match dir.file_name() {
+                    "" => dir.resolve()?.file_name().to_owned(), // relative dirs like ".".
+                    x => x.to_owned(),
+                };
+                if !name.trim().is_empty() {
+                    m.new_name = CASE_FN.get().unwrap()(name.trim()); // the root dir has no name.
+                }
+            }
+        }
+
         // step: apply naming rules.
-        let blocked = self.naming.compile()?.apply(&mut medias);
+        let mut rules = (*self.rules.take().unwrap())?;
+        let mut blocked = rules.apply(&mut medias);
+
+        // step: edit comments.
+        self.edit_comments(&mut medias)?;
+
+        // step: map names to their canonical ones, and group names that share an alias.
+        map_aliases(&mut medias, &self.aliases()?);
 
         // step: reset names if forcing a new one.
         if let Some(force) = &self.force {
@@ -120,18 +274,244 @@ impl Refine for Rebuild {
             });
         }
 
+        // step: fold group names on case-insensitive file systems, so kept cases can't clash.
+        // casing recipes keep the case only for the names, so their groups are always folded.
+        if self.case || rules.has_casing() {
+            let fold = !self.case || self.fold_case || {
+                let fold = parents_case_insensitive(medias.iter().map(|m| &m.entry));
+                if fold {
+                    eprintln!("Enabling fold-case mode due to case-insensitive file system.\n");
+                }
+                fold
+            };
+            if fold {
+                medias.iter_mut().for_each(|m| {
+                    m.group_name = Some(m.group().to_lowercase());
+                });
+            }
+        }
+
+        // step: merge near-identical group names, if the user agrees.
+        if self.fuzzy {
+            let merges = fuzzy_merges(&medias, self.threshold);
+            if !merges.is_empty() {
+                println!("fuzzy matches:");
+                merges.iter().for_each(|(leader, (_, members))| {
+                    let members = members
+                        .iter()
+                        .map(|(m, sim)| format!("{m:?} ({:.1}%)", sim * 100.0))
+                        .collect::<Vec<_>>();
+                    println!("  {leader:?} <-- {}", members.join(", "));
+                });
+                println!();
+                let merge = match self.yes {
+                    true => Ok(()),
+                    false => utils::prompt_yes_no("merge similar names?"),
+                };
+                match merge {
+                    Ok(()) => {
+                        let targets = merges
+                            .iter()
+                            .flat_map(|(leader, (name, members))| {
+                                members
+                                    .iter()
+                                    .map(move |(m, _)| (m.clone(), (leader, name)))
+                            })
+                            .collect::<HashMap<_, _>>();
+                        medias.iter_mut().for_each(|m| {
+                            if let Some(&(leader, name)) = targets.get(m.group()) {
+                                m.group_name = Some(leader.clone());
+                                m.new_name.clone_from(name);
+                            }
+                        });
+                    }
+                    Err(PromptError::No) => {}
+                    Err(err) => return Err(err.into()),
+                }
+                println!();
+            }
+        }
+
         // step: sort medias according to partial or full mode.
+        self.sort(&mut medias);
+
+        // step: generate new names.
+        let names = self.generate(&mut medias);
+
+        utils::aborted()?;
+
+        // step: settle changes, and display the results.
+        medias.retain(|m| m.new_name != m.entry.file_name());
+        if self.review && !medias.is_empty() {
+            // before the sidecars are attached, so they follow the decisions.
+            let fold = self.fold_case || parents_case_insensitive(medias.iter().map(|m| &m.entry));
+            review(&mut medias, fold)?;
+        }
+        blocked += bundles.attach(&mut medias, |_, entry, new_name| Media {
+            new_name,
+            ..Media::new(entry)
+        });
+        medias.iter().for_each(|m| {
+            println!("{} --> {}", m.entry, m.new_name);
+            print!("{}", rules.display_trace(&m.entry));
+        });
+
+        // step: display a summary receipt.
+        if !medias.is_empty() || blocked > 0 {
+            println!();
+        }
+        println!("total files: {total_files} ({} unique names)", names.unique);
+        println!("  changes: {}", medias.len());
+        println!("  blocked: {blocked}");
+        print!("{}", rules.display_unused());
+        if self.alias_report {
+            print!("{}", names.display_aliases());
+        }
+        if self.comment_report {
+            print!("{}", names.display_comments());
+        }
+        if medias.is_empty() {
+            return Ok(());
+        }
+
+        // step: apply changes if the user agrees.
+        if !self.review && !self.yes && self.save_plan.is_none() {
+            utils::prompt_yes_no("apply changes?")?;
+        }
+        if let Some(path) = &self.save_plan {
+            return Plan::save(path, "rebuild", OpKind::Rename, &medias);
+        }
+        FileOps::rename_move(&mut medias);
+
+        match medias.is_empty() {
+            _ if !utils::is_running() => {
+                println!("aborted, {} remaining; run again to resume", medias.len())
+            }
+            true => println!("done"),
+            false => println!("found {} errors", medias.len()),
+        }
+        Ok(())
+    }
+}
+
+impl Rebuild {
+    /// Report gaps, duplicated sequences, mixed case names, and files not in collection format.
+    fn check(&self, medias: &[Media]) -> Result<()> {
+        // step: display the issues found, under their titles.
+        let (issues, total) = self.issues(medias);
+        let mut title = "";
+        issues.iter().for_each(|issue| {
+            if issue.title() != title {
+                title = issue.title();
+                println!("{title}:");
+            }
+            print!("{issue}");
+        });
+
+        // step: display a summary receipt.
+        if !issues.is_empty() {
+            println!();
+        }
+        println!("total files: {} ({total} collections)", medias.len());
+        println!("  issues: {}", issues.len());
+        match issues.len() {
+            0 => Ok(()),
+            n => Err(anyhow!("found {n} issues")),
+        }
+    }
+
+    /// Find the issues of the collections within their scopes, along with the number of collections.
+    fn issues<'a>(&self, medias: &'a [Media]) -> (Vec<Issue<'a>>, usize) {
+        let (outsiders, collections) = collections(medias, self.scope);
+        let mut issues = outsiders
+            .into_iter()
+            .map(Issue::Outsider)
+            .collect::<Vec<_>>();
+        issues.extend(collections.iter().filter_map(|((scope, name), c)| {
+            Some(Issue::Gaps(
+                scope.clone(),
+                name.clone(),
+                display_gaps(&c.seqs)?,
+            ))
+        }));
+        issues.extend(collections.iter().flat_map(|((scope, name), c)| {
+            c.seqs
+                .iter()
+                .filter(|(_, es)| es.len() > 1)
+                .map(|(&seq, es)| {
+                    let mut es = es.clone();
+                    es.sort_unstable();
+                    let name = self.scheme.format(name, None, seq, "", "");
+                    Issue::Duplicated(scope.clone(), name, es)
+                })
+        }));
+        issues.extend(collections.iter().filter(|(_, c)| c.names.len() > 1).map(
+            |((scope, name), c)| {
+                let names = c.names.iter().copied().collect();
+                Issue::MixedCase(scope.clone(), name.clone(), names)
+            },
+        ));
+        (issues, collections.len())
+    }
+
+    /// Set, clear, and replace comments, normalizing only the ones that were edited.
+    fn edit_comments(&self, medias: &mut [Media]) -> Result<()> {
+        let comment_rules = self
+            .comment_replace
+            .iter()
+            .map(|(re, to)| {
+                Regex::new(&format!("(?i){re}"))
+                    .map(|re| (re, to))
+                    .with_context(|| format!("compiling regex: {re:?}"))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let edited = self.comment_set.is_some() || !comment_rules.is_empty();
+        medias.iter_mut().for_each(|m| {
+            if let Some(comment) = &self.comment_set {
+                m.comment.clone_from(comment);
+            } else if self.comment_clear {
+                m.comment.clear();
+            }
+            comment_rules.iter().for_each(|(re, to)| {
+                if let Cow::Owned(x) = re.replace_all(&m.comment, *to) {
+                    m.comment = x;
+                }
+            });
+            if edited {
+                m.comment = normalize_comment(&m.comment); // existing comments are kept as is.
+            }
+        });
+        Ok(())
+    }
+
+    /// Sort the medias by scope and group, then by their current sequences in partial mode, and
+    /// finally by the requested order.
+    fn sort(&self, medias: &mut [Media]) {
         let seq = match self.partial {
             true => |m: &Media| m.seq.unwrap_or(usize::MAX), // no sequence goes to the end in partial mode.
             false => |_: &Media| 0,                          // ignore sequences in full mode.
         };
+        let order = |m: &Media, n: &Media| match self.order_by {
+            OrderBy::Created => m.created.cmp(&n.created),
+            OrderBy::Modified => m.modified.cmp(&n.modified),
+            OrderBy::NameDate => {
+                (m.name_date.is_none(), m.name_date, m.created) // no date goes last.
+                    .cmp(&(n.name_date.is_none(), n.name_date, n.created))
+            }
+            OrderBy::Natural => natural_cmp(m.entry.file_name(), n.entry.file_name()),
+        };
         medias.sort_unstable_by(|m, n| {
             // unfortunately, some file systems have low-resolution creation time, HFS+ for example,
             // so m.seq is used to disambiguate `created`, which seems to repeat a lot sometimes.
-            (m.group(), seq(m), m.created, m.seq).cmp(&(n.group(), seq(n), n.created, n.seq))
+            (&m.scope, m.group(), seq(m))
+                .cmp(&(&n.scope, n.group(), seq(n)))
+                .then_with(|| order(m, n))
+                .then(m.seq.cmp(&n.seq))
         });
+    }
 
-        // step: generate new names.
+    /// Generate the new names of the sorted medias, with sequences for each group.
+    fn generate(&self, medias: &mut [Media]) -> Names {
         let name_idx = if self.simple {
             |_g: &[Media]| 0 // all the names are exactly the same within a group.
         } else if self.case {
@@ -157,66 +537,342 @@ impl Refine for Rebuild {
             true => |m: &Media, last_seq: usize| m.seq.unwrap_or_else(|| last_seq + 1),
             false => |_: &Media, last_seq: usize| last_seq + 1,
         };
-        let mut unique_names = 0;
+        let mut names = Names::default();
         medias
-            .chunk_by_mut(|m, n| m.group() == n.group())
+            .chunk_by_mut(|m, n| (&m.scope, m.group()) == (&n.scope, n.group()))
             .for_each(|g| {
-                unique_names += 1;
+                names.unique += 1;
                 let base = std::mem::take(&mut g[name_idx(g)].new_name); // must be taken because `g` will be modified below.
+                if self.alias_report {
+                    let (count, aliases) = names.aliases.entry(base.clone()).or_default();
+                    *count += g.len();
+                    g.iter()
+                        .filter_map(|m| m.alias.clone())
+                        .for_each(|a| *aliases.entry(a).or_default() += 1);
+                }
+                if self.comment_report {
+                    let counts = names.comments.entry(base.clone()).or_default();
+                    g.iter()
+                        .filter(|m| !m.comment.is_empty())
+                        .for_each(|m| *counts.entry(m.comment.trim().to_owned()).or_default() += 1);
+                }
                 let mut seq = 0; // keep track of the last sequence number used.
                 g.iter_mut().for_each(|m| {
                     seq = seq_gen(m, seq);
-                    let dot = if m.ext.is_empty() { "" } else { "." };
-                    m.new_name = format!("{base}~{seq}{}{dot}{}", m.comment, m.ext);
+                    m.new_name =
+                        self.scheme
+                            .format(&base, m.alias.as_deref(), seq, &m.comment, m.ext);
                 });
             });
+        names
+    }
 
-        utils::aborted()?;
+    /// Load the alias mappings from files and the command line, keyed by the lowercase old names.
+    fn aliases(&self) -> Result<HashMap<String, String>> {
+        let mut aliases = HashMap::new();
+        for path in &self.alias_file {
+            let text = fs::read_to_string(path).with_context(|| format!("reading {path:?}"))?;
+            for (line, n) in text.lines().zip(1..).map(|(l, n)| (l.trim(), n)) {
+                if line.is_empty() || line.starts_with('#') {
+                    continue;
+                }
+                let (old, new) = utils::parse_key_value::<String, String>(line)
+                    .with_context(|| format!("line {n}: {line:?}"))
+                    .with_context(|| format!("in alias file {path:?}"))?;
+                aliases.insert(old.trim().to_lowercase(), new.trim().to_owned());
+            }
+        }
+        aliases.extend(
+            self.alias
+                .iter()
+                .map(|(k, v)| (k.to_lowercase(), v.to_owned())),
+        );
+        Ok(aliases)
+    }
+}
 
-        // step: settle changes, and display the results.
-        medias.retain(|m| m.new_name != m.entry.file_name());
-        medias
-            .iter()
-            .for_each(|m| println!("{} --> {}", m.entry, m.new_name));
+/// An issue found by the check, with the scope directory and name of its collection.
+#[derive(Debug)]
+enum Issue<'a> {
+    /// A file not in collection format.
+    Outsider(&'a Entry),
+    /// The missing sequences of a collection, like "3, 5-7".
+    Gaps(Option<Entry>, String, String),
+    /// The files that share a sequence, with the name they share.
+    Duplicated(Option<Entry>, String, Vec<&'a Entry>),
+    /// The different cases of a collection name.
+    MixedCase(Option<Entry>, String, Vec<&'a str>),
+}
 
-        // step: display a summary receipt.
-        if !medias.is_empty() || blocked > 0 {
-            println!();
-        }
-        println!("total files: {total_files} ({unique_names} unique names)");
-        println!("  changes: {}", medias.len());
-        println!("  blocked: {blocked}");
-        if medias.is_empty() {
-            return Ok(());
+impl Issue<'_> {
+    fn title(&self) -> &'static str {
+        match self {
+            Issue::Outsider(_) => "not in collection format",
+            Issue::Gaps(..) => "gaps",
+            Issue::Duplicated(..) => "duplicated sequences",
+            Issue::MixedCase(..) => "mixed case names",
         }
+    }
+}
 
-        // step: apply changes if the user agrees.
-        if !self.yes {
-            utils::prompt_yes_no("apply changes?")?;
-        }
-        FileOps::rename_move(&mut medias);
-        if medias.is_empty() {
-            println!("done");
-            return Ok(());
+impl Display for Issue<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Issue::Outsider(entry) => writeln!(f, "  {entry}"),
+            Issue::Gaps(scope, name, gaps) => {
+                writeln!(f, "  {}{name}: {gaps}", display_scope(scope))
+            }
+            Issue::Duplicated(scope, name, entries) => {
+                writeln!(
+                    f,
+                    "  {}{name}: {} files",
+                    display_scope(scope),
+                    entries.len()
+                )?;
+                entries.iter().try_for_each(|e| writeln!(f, "    {e}"))
+            }
+            Issue::MixedCase(scope, name, names) => {
+                writeln!(f, "  {}{name}: {}", display_scope(scope), names.join(", "))
+            }
         }
+    }
+}
 
-        // step: fix file already exists errors.
-        println!("attempting to fix {} errors", medias.len());
-        medias.iter_mut().for_each(|m| {
-            let temp = format!("__refine+{}__", m.new_name);
-            let dest = m.entry.with_file_name(&temp);
-            match fs::rename(&m.entry, &dest) {
-                Ok(()) => m.entry = dest,
-                Err(err) => eprintln!("error: {err}: {} --> {temp:?}", m.entry),
+/// The number of unique names generated, along with the requested reports by name.
+#[derive(Debug, Default)]
+struct Names {
+    unique: usize,
+    /// The number of files and the counts of each alias.
+    aliases: BTreeMap<String, (usize, BTreeMap<String, usize>)>,
+    /// The counts of each comment.
+    comments: BTreeMap<String, BTreeMap<String, usize>>,
+}
+
+impl Names {
+    /// Display the canonical names with their file counts and aliases.
+    fn display_aliases(&self) -> String {
+        let mut out = "  canonical names:\n".to_owned();
+        self.aliases.iter().for_each(|(name, (count, aliases))| {
+            let aliases = aliases
+                .iter()
+                .map(|(a, n)| format!("{a} ({n})"))
+                .collect::<Vec<_>>();
+            match aliases.is_empty() {
+                true => out += &format!("    {name}: {count}\n"),
+                false => out += &format!("    {name}: {count}, aliases: {}\n", aliases.join(", ")),
             }
         });
-        FileOps::rename_move(&mut medias);
+        out
+    }
 
-        match medias.is_empty() {
-            true => println!("done"),
-            false => println!("still {} errors, giving up", medias.len()),
+    /// Display the distinct comments of each name with their counts.
+    fn display_comments(&self) -> String {
+        let mut out = "  comments:\n".to_owned();
+        self.comments.iter().for_each(|(name, counts)| {
+            let counts = counts
+                .iter()
+                .map(|(c, n)| format!("{c:?} ({n})"))
+                .collect::<Vec<_>>();
+            match counts.is_empty() {
+                true => out += &format!("    {name}: none\n"),
+                false => out += &format!("    {name}: {}\n", counts.join(", ")),
+            }
+        });
+        out
+    }
+}
+
+/// The sequences of a collection with their files, and the different cases of its name.
+#[derive(Debug, Default)]
+struct Collection<'a> {
+    seqs: BTreeMap<usize, Vec<&'a Entry>>,
+    names: BTreeSet<&'a str>,
+}
+
+/// The collections by scope directory and lowercase name.
+type Collections<'a> = BTreeMap<(Option<Entry>, String), Collection<'a>>;
+
+/// Gather the sequences of each collection by scope directory and lowercase name, along with the
+/// files not in collection format.
+fn collections(medias: &[Media], scope: Scope) -> (Vec<&Entry>, Collections<'_>) {
+    let (mut outsiders, mut collections) = (vec![], Collections::new());
+    medias.iter().for_each(|m| {
+        let (name, _, seq, ..) = m.entry.collection_parts();
+        let Some(seq) = seq else {
+            outsiders.push(&m.entry);
+            return;
+        };
+        let scope = scope.depth().and_then(|d| ancestor(&m.entry, d));
+        let c = collections.entry((scope, name.to_lowercase())).or_default();
+        c.seqs.entry(seq).or_default().push(&m.entry);
+        c.names.insert(name);
+    });
+    (outsiders, collections)
+}
+
+/// Map names to their canonical ones, keeping the old names as aliases, then group the names that
+/// share an alias under their most common canonical name.
+fn map_aliases(medias: &mut [Media], aliases: &HashMap<String, String>) {
+    medias.iter_mut().for_each(|m| {
+        if let Some(canonical) = aliases.get(&m.new_name.to_lowercase()) {
+            let old = std::mem::replace(&mut m.new_name, CASE_FN.get().unwrap()(canonical));
+            // the mapped away name wins over a previous alias, since it is what links the
+            // group; only a mere change of case keeps the previous one.
+            if old.to_lowercase() != m.new_name.to_lowercase() {
+                m.alias = Some(old);
+            }
+        }
+    });
+
+    let mut by_alias = HashMap::<_, HashMap<_, usize>>::new();
+    medias
+        .iter()
+        .filter_map(|m| Some((m.alias.clone()?, m.new_name.clone())))
+        .for_each(|(alias, name)| {
+            *by_alias.entry(alias).or_default().entry(name).or_default() += 1
+        });
+    let canonical = by_alias
+        .into_iter()
+        .map(|(alias, names)| {
+            let (name, _) = names
+                .into_iter()
+                .max_by(|(n, c), (o, d)| c.cmp(d).then(o.cmp(n))) // ties go to the first name.
+                .unwrap();
+            (alias, name)
+        })
+        .collect::<HashMap<_, _>>();
+    medias.iter_mut().for_each(|m| {
+        if let Some(name) = m.alias.as_ref().and_then(|a| canonical.get(a)) {
+            m.new_name.clone_from(name);
+        }
+        if m.alias.as_ref() == Some(&m.new_name) {
+            m.alias = None; // an alias can't be its own canonical name.
+        }
+    });
+}
+
+/// Normalize a comment, so it is either empty or trimmed with a single leading space.
+fn normalize_comment(comment: &str) -> String {
+    match comment.trim() {
+        "" => String::new(),
+        x => format!(" {}", x.replace(['/', '\\'], "-")), // comments can't introduce path separators.
+    }
+}
+
+/// Display the missing sequences as ranges, like "3, 5-7", or None if there are no gaps.
+fn display_gaps<T>(seqs: &BTreeMap<usize, T>) -> Option<String> {
+    let mut gaps = vec![];
+    let mut next = 1;
+    for &seq in seqs.keys() {
+        match seq.saturating_sub(next) {
+            0 => {}
+            1 => gaps.push(next.to_string()),
+            _ => gaps.push(format!("{next}-{}", seq - 1)),
+        }
+        next = seq + 1;
+    }
+    (!gaps.is_empty()).then(|| gaps.join(", "))
+}
+
+/// Display a scope directory as a prefix, or nothing if global.
+fn display_scope(scope: &Option<Entry>) -> String {
+    scope.as_ref().map(|s| s.to_string()).unwrap_or_default() // dirs are displayed with a trailing slash.
+}
+
+/// Find groups with near-identical names, merging each into the one with the most files.
+///
+/// It returns the leader groups, with their new names and merged groups with their similarity.
+fn fuzzy_merges(
+    medias: &[Media],
+    threshold: f64,
+) -> BTreeMap<String, (String, Vec<(String, f64)>)> {
+    // only letters and digits are significant, so "foo-bar" and "foobar" are the same.
+    let key = |s: &str| {
+        s.chars()
+            .filter(|c| c.is_alphanumeric())
+            .flat_map(char::to_lowercase)
+            .collect::<String>()
+    };
+    // names that only differ in their numbers are probably a sequence, like "trip1" and "trip2".
+    let sequential = |a: &str, b: &str| {
+        let strip = |s: &str| s.replace(|c: char| c.is_ascii_digit(), "");
+        let (sa, sb) = (strip(a), strip(b));
+        sa == sb && sa.len() < a.len() && sb.len() < b.len()
+    };
+
+    let mut groups = HashMap::<_, (usize, &str)>::new();
+    medias.iter().for_each(|m| {
+        groups.entry(m.group()).or_insert((0, &m.new_name)).0 += 1;
+    });
+    let mut groups = groups.into_iter().collect::<Vec<_>>();
+    groups.sort_unstable_by(|(g, (c, _)), (h, (d, _))| d.cmp(c).then(g.cmp(h)));
+
+    let mut leaders = Vec::<(&str, &str, String)>::new();
+    let mut merges = BTreeMap::<String, (String, Vec<_>)>::new();
+    for (group, (_, name)) in groups {
+        let k = key(group);
+        let best = leaders
+            .iter()
+            .filter(|(.., lk)| !sequential(lk, &k))
+            .map(|l| (l, utils::similarity(&l.2, &k)))
+            .filter(|&(_, sim)| sim >= threshold)
+            .max_by(|(_, s1), (_, s2)| s1.total_cmp(s2));
+        match best {
+            Some(((leader, leader_name, _), sim)) => merges
+                .entry(leader.to_string())
+                .or_insert_with(|| (leader_name.to_string(), vec![]))
+                .1
+                .push((group.to_owned(), sim)),
+            None => leaders.push((group, name, k)),
+        }
+    }
+    merges
+}
+
+/// Find the Nth ancestor directory of an entry, or the topmost one if there aren't enough.
+fn ancestor(entry: &Entry, depth: usize) -> Option<Entry> {
+    std::iter::successors(entry.parent(), Entry::parent)
+        .take_while(|p| !p.as_os_str().is_empty()) // relative paths end with an empty one.
+        .take(depth)
+        .last()
+}
+
+impl Scope {
+    /// The depth of the scope directories, or None if global.
+    fn depth(self) -> Option<usize> {
+        match self {
+            Scope::Global => None,
+            Scope::Parent => Some(1),
+            Scope::Depth(n) => Some(n),
+        }
+    }
+}
+
+impl FromStr for Scope {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "global" | "g" => Ok(Scope::Global),
+            "parent" | "p" => Ok(Scope::Parent),
+            x => match x.strip_prefix("depth=").map(str::parse) {
+                Some(Ok(0)) => Err("depth must be at least 1".to_owned()),
+                Some(Ok(n)) => Ok(Scope::Depth(n)),
+                _ => Err(format!(
+                    "unknown scope: {x:?} (use global, parent, or depth=N)"
+                )),
+            },
+        }
+    }
+}
+
+impl Display for Scope {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Scope::Global => write!(f, "global"),
+            Scope::Parent => write!(f, "parent"),
+            Scope::Depth(n) => write!(f, "depth={n}"),
         }
-        Ok(())
     }
 }
 
@@ -235,16 +891,352 @@ impl TryFrom<Entry> for Media {
     type Error = (Entry, anyhow::Error);
 
     fn try_from(entry: Entry) -> Result<Self, Self::Error> {
-        let (name, _, seq, comment, ext) = entry.collection_parts();
-        let created = entry.metadata().map_or(None, |m| m.created().ok());
-        Ok(Media {
+        Ok(Media::new(entry))
+    }
+}
+
+impl Media {
+    fn new(entry: Entry) -> Self {
+        let (name, alias, seq, comment, ext) = entry.collection_parts();
+        let metadata = entry.metadata().ok();
+        let created = metadata.as_ref().and_then(|m| m.created().ok());
+        let modified = metadata.as_ref().and_then(|m| m.modified().ok());
+        Media {
             new_name: CASE_FN.get().unwrap()(name.trim()),
+            scope: None,
             group_name: None,
+            alias: alias.map(CASE_FN.get().unwrap()),
             seq,
             comment: comment.to_string(),
             ext: utils::intern(ext),
             created: created.unwrap_or(SystemTime::now()),
+            modified: modified.unwrap_or(SystemTime::now()),
+            name_date: name_date(entry.filename_parts().0),
             entry,
-        })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::{Command, FromArgMatches};
+    use std::path::Path;
+    use std::time::Duration;
+
+    /// Parse the options from a command line.
+    fn rebuild(args: &[&str]) -> Rebuild {
+        let cmd = Rebuild::augment_args(Command::new("rebuild"));
+        let matches = cmd.get_matches_from(std::iter::once(&"rebuild").chain(args));
+        Rebuild::from_arg_matches(&matches).unwrap()
+    }
+
+    /// Create the medias of some ".jpg" files, which don't need to exist.
+    fn medias(stems: &[&str]) -> Vec<Media> {
+        CASE_FN.get_or_init(|| str::to_lowercase);
+        stems
+            .iter()
+            .map(|n| Media::new(Entry::try_new(format!("{n}.jpg"), false).unwrap()))
+            .collect()
+    }
+
+    #[test]
+    fn scope_parse() {
+        #[track_caller]
+        fn case(s: &str, out: Result<Scope, ()>) {
+            assert_eq!(s.parse::<Scope>().map_err(|_| ()), out);
+            if let Ok(scope) = out {
+                assert_eq!(scope.to_string().parse(), Ok(scope));
+            }
+        }
+
+        case("global", Ok(Scope::Global));
+        case("G", Ok(Scope::Global));
+        case("parent", Ok(Scope::Parent));
+        case("p", Ok(Scope::Parent));
+        case("depth=1", Ok(Scope::Depth(1)));
+        case("Depth=3", Ok(Scope::Depth(3)));
+        case("depth=0", Err(()));
+        case("depth=-1", Err(()));
+        case("depth", Err(()));
+        case("depth=x", Err(()));
+        case("nope", Err(()));
+    }
+
+    #[test]
+    fn scope_ancestor() {
+        #[track_caller]
+        fn case(path: &str, depth: usize, out: Option<&str>) {
+            let entry = Entry::try_new(path, false).unwrap();
+            assert_eq!(ancestor(&entry, depth).as_deref(), out.map(Path::new));
+        }
+
+        case("a/b/c/f.jpg", 1, Some("a/b/c"));
+        case("a/b/c/f.jpg", 2, Some("a/b"));
+        case("a/b/c/f.jpg", 3, Some("a"));
+        case("a/b/c/f.jpg", 5, Some("a"));
+        case("f.jpg", 1, None);
+        case("/a/f.jpg", 1, Some("/a"));
+        case("/a/f.jpg", 3, Some("/"));
+    }
+
+    #[test]
+    fn fuzzy_groups() {
+        #[track_caller]
+        fn case(names: &[&str], threshold: f64, out: &[(&str, &[&str])]) {
+            let merges = fuzzy_merges(&medias(names), threshold)
+                .into_iter()
+                .map(|(leader, (_, ms))| (leader, ms.into_iter().map(|(g, _)| g).collect()))
+                .collect::<Vec<(String, Vec<String>)>>();
+            let out = out
+                .iter()
+                .map(|(l, ms)| (l.to_string(), ms.iter().map(|g| g.to_string()).collect()))
+                .collect::<Vec<(String, Vec<String>)>>();
+            assert_eq!(merges, out);
+        }
+
+        case(
+            &["foo-bar", "foo-bar", "foobar"],
+            0.8,
+            &[("foo-bar", &["foobar"])],
+        );
+        case(&["foobar", "foo-bar"], 0.8, &[("foo-bar", &["foobar"])]);
+        case(
+            &["fo0 bar", "foo bar", "foo bar"],
+            0.8,
+            &[("foo bar", &["fo0 bar"])],
+        );
+        case(&["fo0 bar", "foo bar", "foo bar"], 0.9, &[]);
+        case(
+            &["Foo_Bar", "foo bar", "foo bar", "fo0 bar"],
+            0.8,
+            &[("foo bar", &["fo0 bar", "foo_bar"])],
+        );
+        case(&["trip1", "trip2", "trip3"], 0.5, &[]);
+        case(&["cats", "dogs"], 0.8, &[]);
+    }
+
+    #[test]
+    fn check_issues() {
+        #[track_caller]
+        fn case(args: &[&str], stems: &[&str], out: &[&str]) {
+            let medias = medias(stems);
+            let (issues, _) = rebuild(args).issues(&medias);
+            let key = |scope: &Option<Entry>, name: &str| match scope {
+                Some(s) => format!("{}/{name}", s.to_str()),
+                None => name.to_owned(),
+            };
+            let issues = issues
+                .iter()
+                .map(|issue| match issue {
+                    Issue::Outsider(e) => format!("outsider {}", e.to_str()),
+                    Issue::Gaps(s, name, gaps) => format!("gaps {}: {gaps}", key(s, name)),
+                    Issue::Duplicated(s, name, es) => format!("dup {}: {}", key(s, name), es.len()),
+                    Issue::MixedCase(s, name, names) => {
+                        format!("mixed {}: {}", key(s, name), names.join(", "))
+                    }
+                })
+                .collect::<Vec<_>>();
+            assert_eq!(issues, out);
+        }
+
+        case(&[], &["foo~1", "foo~2", "foo~3"], &[]);
+        case(
+            &[],
+            &["foo~1", "foo~4", "bar~2", "baz"],
+            &["outsider baz.jpg", "gaps bar: 1", "gaps foo: 2-3"],
+        );
+        case(
+            &[],
+            &["foo~1", "foo~1 copy", "Foo~2", "foo~5"],
+            &["gaps foo: 3-4", "dup foo~1: 2", "mixed foo: Foo, foo"],
+        );
+        case(&[], &["a/foo~1", "b/foo~2", "b/foo~3"], &[]);
+        case(
+            &["--scope", "parent"],
+            &["a/foo~1", "b/foo~2", "b/foo~3"],
+            &["gaps b/foo: 1"],
+        );
+        case(&["--scope", "parent"], &["x/a/foo~1", "x/b/foo~1"], &[]);
+        case(
+            &["--scope", "depth=2"],
+            &["x/a/foo~1", "x/b/foo~1"],
+            &["dup x/foo~1: 2"],
+        );
+        case(&[], &["foo bar~1", "foo bar~1 copy"], &["dup foo bar~1: 2"]);
+    }
+
+    #[test]
+    fn alias_mapping() {
+        #[track_caller]
+        fn case(args: &[&str], stems: &[&str], out: &[&str], report: &[&str]) {
+            let opt = rebuild(args);
+            let run = |stems: &[&str]| {
+                let mut medias = medias(stems);
+                map_aliases(&mut medias, &opt.aliases().unwrap());
+                opt.sort(&mut medias);
+                let names = opt.generate(&mut medias);
+                let mut new = medias.into_iter().map(|m| m.new_name).collect::<Vec<_>>();
+                new.sort();
+                (new, names)
+            };
+
+            let (new, names) = run(stems);
+            assert_eq!(new, out);
+            let lines = names.display_aliases();
+            assert_eq!(
+                lines.lines().skip(1).map(str::trim).collect::<Vec<_>>(),
+                report
+            );
+
+            // step: running again on the new names must keep them.
+            let stems = new
+                .iter()
+                .map(|n| n.trim_end_matches(".jpg"))
+                .collect::<Vec<_>>();
+            assert_eq!(run(&stems).0, new);
+        }
+
+        let args = ["-s", "-p", "-o", "n", "--alias-report"];
+        case(
+            &[&args[..], &["--alias", "the beatles=beatles"]].concat(),
+            &["the beatles", "beatles"],
+            &["beatles+the beatles~2.jpg", "beatles~1.jpg"],
+            &["beatles: 2, aliases: the beatles (1)"],
+        );
+        case(
+            &[&args[..], &["--alias", "The Beatles=beatles"]].concat(),
+            &["the beatles+fab four~3", "beatles~1"],
+            &["beatles+the beatles~3.jpg", "beatles~1.jpg"],
+            &["beatles: 2, aliases: the beatles (1)"],
+        );
+        case(
+            &args,
+            &["beatles+fab four~1", "the beatles+the fabs~2", "beatles~3"],
+            &[
+                "beatles+fab four~1.jpg",
+                "beatles~3.jpg",
+                "the beatles+the fabs~2.jpg",
+            ],
+            &[
+                "beatles: 2, aliases: fab four (1)",
+                "the beatles: 1, aliases: the fabs (1)",
+            ],
+        );
+        case(
+            &args,
+            &[
+                "beatles+fab four~1",
+                "beatles+fab four~2",
+                "the beatles+fab four~3",
+            ],
+            &[
+                "beatles+fab four~1.jpg",
+                "beatles+fab four~2.jpg",
+                "beatles+fab four~3.jpg",
+            ],
+            &["beatles: 3, aliases: fab four (3)"],
+        );
+    }
+
+    #[test]
+    fn comments() {
+        #[track_caller]
+        fn case(args: &[&str], comment: &str, out: &str) {
+            let opt = rebuild(args);
+            let mut medias = medias(&[&format!("foo~1{comment}")]);
+            opt.edit_comments(&mut medias).unwrap();
+            assert_eq!(medias[0].comment, out);
+        }
+
+        case(&[], "  odd take ", "  odd take "); // kept as is.
+        case(&[], "", "");
+        case(&["--comment-clear"], " odd", "");
+        case(
+            &["--comment-set", " best/of\\all  "],
+            " cut",
+            " best-of-all",
+        );
+        case(&["--comment-set", "  "], " cut", "");
+        case(
+            &["--comment-replace", "raw=edit"],
+            " RAW take",
+            " edit take",
+        );
+        case(&["--comment-replace", "take="], " take", "");
+        case(
+            &["--comment-replace", "(\\d+)=#$1"],
+            "  take 2 ",
+            " take #2",
+        );
+        case(
+            &["--comment-set", "cut 1", "--comment-replace", "cut=take"],
+            " raw",
+            " take 1",
+        );
+    }
+
+    #[test]
+    fn normalize_comments() {
+        #[track_caller]
+        fn case(comment: &str, out: &str) {
+            assert_eq!(normalize_comment(comment), out);
+        }
+
+        case("", "");
+        case("   ", "");
+        case("cool", " cool");
+        case("  cool cut  ", " cool cut");
+        case("a/b\\c", " a-b-c");
+        case("\tbest\n", " best");
+    }
+
+    #[test]
+    fn sort_orders() {
+        #[track_caller]
+        fn case(args: &[&str], files: &[(&str, u64, u64)], out: &[&str]) {
+            let stems = files.iter().map(|&(stem, ..)| stem).collect::<Vec<_>>();
+            let mut medias = medias(&stems);
+            medias
+                .iter_mut()
+                .zip(files)
+                .for_each(|(m, &(_, created, modified))| {
+                    m.created = SystemTime::UNIX_EPOCH + Duration::from_secs(created);
+                    m.modified = SystemTime::UNIX_EPOCH + Duration::from_secs(modified);
+                });
+            rebuild(args).sort(&mut medias);
+            let names = medias
+                .iter()
+                .map(|m| m.entry.file_name())
+                .collect::<Vec<_>>();
+            let out = out.iter().map(|o| format!("{o}.jpg")).collect::<Vec<_>>();
+            assert_eq!(names, out);
+        }
+
+        let files = [("foo~1", 2, 1), ("foo~3", 3, 3), ("foo~2", 1, 2)];
+        case(&[], &files, &["foo~2", "foo~1", "foo~3"]);
+        case(&["-o", "modified"], &files, &["foo~1", "foo~2", "foo~3"]);
+        let files = [("foo~100", 1, 1), ("foo~9", 3, 3), ("foo~10", 2, 2)];
+        case(&["-o", "natural"], &files, &["foo~9", "foo~10", "foo~100"]);
+        case(
+            &["-p"],
+            &[("foo", 1, 1), ("foo~5", 3, 3), ("foo~2", 2, 2)],
+            &["foo~2", "foo~5", "foo"],
+        );
+        case(
+            &["-o", "name-date"],
+            &[
+                ("foo~1 2024-06-02", 1, 1),
+                ("foo~2 no date", 0, 0),
+                ("foo~3 2024-06-01 at 3.30.12 PM", 3, 3),
+                ("foo~4 20240601_090000", 2, 2),
+            ],
+            &[
+                "foo~4 20240601_090000",
+                "foo~3 2024-06-01 at 3.30.12 PM",
+                "foo~1 2024-06-02",
+                "foo~2 no date",
+            ],
+        );
     }
 }
@@ -1,20 +1,51 @@
 use crate::commands::Refine;
 use crate::entries::{Entry, TraversalMode};
-use crate::medias::{FileOps, Naming};
+use crate::medias::{
+    FileOps, Naming, OpKind, Plan, Sidecars, Template, edit_names, fold_key,
+    parents_case_insensitive, review,
+};
 use crate::utils;
 use crate::{impl_new_name, impl_new_name_mut, impl_source_entry};
 use anyhow::Result;
+use clap::builder::NonEmptyStringValueParser;
 use clap::{Args, ValueEnum};
 use std::cmp::Reverse;
 use std::fmt::{Display, Write};
+use std::path::{Path, PathBuf};
 
 #[derive(Debug, Args)]
 pub struct Rename {
     #[command(flatten)]
     naming: Naming,
+    #[command(flatten)]
+    sidecars: Sidecars,
+    /// Build new file names from a template, before the naming rules; the extension is always kept.
+    ///
+    /// Placeholders: {stem}, {kind} (video, audio, image...), {collection} (the name without alias,
+    /// sequence, or comment), {parent}, {n[:WIDTH]} (per folder counter), {mtime[:FMT]}, {size},
+    /// {taken[:FMT]}, {model}, {gps} (EXIF tags), {date[:FMT]} (taken, in the name, or mtime),
+    /// {artist}, {title}, {album}, {track} (ID3 tags), and the capture groups of --match, like {1}
+    /// or {name}. Dates use strftime formats, like %Y-%m-%d.
+    #[arg(short = 't', long, value_name = "STR", value_parser = NonEmptyStringValueParser::new())]
+    template: Option<String>,
+    /// Only apply the template to files whose names match this; its groups become placeholders.
+    #[arg(short = 'm', long, value_name = "REGEX", requires = "template", allow_hyphen_values = true, value_parser = NonEmptyStringValueParser::new())]
+    r#match: Option<String>,
+    /// Edit the new names in $EDITOR after the other rules, vidir-style.
+    #[arg(short = 'E', long)]
+    editor: bool,
     /// How to resolve clashes.
     #[arg(short = 'c', long, default_value_t = Clashes::Sequence, value_name = "STR", value_enum)]
     clashes: Clashes,
+    /// Treat names differing only by case as clashes (auto-detected on case-insensitive file systems).
+    #[arg(long)]
+    fold_case: bool,
+    /// Review each change interactively, to accept, skip, or edit it before applying.
+    #[arg(long, conflicts_with = "yes")]
+    review: bool,
+    /// Save the changes to a plan file instead of applying them, to run later with `refine apply`.
+    #[arg(long, value_name = "FILE")]
+    save_plan: Option<PathBuf>,
     /// Skip the confirmation prompt, useful for automation.
     #[arg(short = 'y', long)]
     yes: bool,
@@ -49,51 +80,99 @@ impl Refine for Rename {
 
     fn refine(&self, mut medias: Vec<Self::Media>) -> Result<()> {
         let total_files = medias.len();
+        let (bundles, mut sidecars) = self.sidecars.detach(&mut medias)?;
+
+        // step: build names from the template.
+        let mut blocked = match &self.template {
+            Some(template) => {
+                Template::parse(template, self.r#match.as_deref())?.apply(&mut medias)
+            }
+            None => 0,
+        };
 
         // step: apply naming rules.
-        let mut blocked = self.naming.compile()?.apply(&mut medias);
+        let mut rules = self.naming.compile()?;
+        blocked += rules.apply(&mut medias);
+        blocked += rules.apply(&mut sidecars); // they are renamed alone if their primaries don't move.
+
+        // step: edit the names in the user's editor, which may also change the extensions.
+        if self.editor {
+            medias.sort_by_cached_key(|m| (m.entry.parent(), m.entry.clone()));
+            let names = medias
+                .iter()
+                .map(|m| {
+                    let dot = if m.ext.is_empty() { "" } else { "." };
+                    (&m.entry, format!("{}{dot}{}", m.new_name, m.ext))
+                })
+                .collect::<Vec<_>>();
+            let edited = edit_names(&names)?;
+            medias
+                .iter_mut()
+                .zip(edited)
+                .for_each(|(m, name)| match name {
+                    None => {
+                        let (stem, ext) = m.entry.filename_parts();
+                        (m.new_name, m.ext) = (stem.trim().to_owned(), utils::intern(ext));
+                    }
+                    Some(name) if m.entry.is_dir() => m.new_name = name,
+                    Some(name) => {
+                        let path = Path::new(&name);
+                        let ext = path.extension().and_then(|e| e.to_str());
+                        let stem = path.file_stem().and_then(|s| s.to_str());
+                        m.ext = utils::intern(ext.unwrap_or_default());
+                        m.new_name = stem.unwrap_or_default().trim().to_owned();
+                    }
+                });
+        }
 
         // step: re-include extension in the names.
         medias
             .iter_mut()
+            .chain(&mut sidecars)
             .filter(|m| !m.ext.is_empty())
             .try_for_each(|m| write!(m.new_name, ".{}", m.ext))?;
 
+        // step: detect case-insensitive file systems.
+        let fold = self.fold_case || {
+            let fold = parents_case_insensitive(medias.iter().map(|m| &m.entry));
+            if fold {
+                eprintln!("Enabling fold-case mode due to case-insensitive file system.\n");
+            }
+            fold
+        };
+        let key = |m: &Media| fold_key(&m.new_name, fold).into_owned();
+        let same = |m: &Media, n: &Media| key(m) == key(n);
+
         // step: clashes resolution.
         let mut clashes = 0;
-        medias.sort_unstable_by(|m, n| {
-            (m.entry.parent(), &m.new_name).cmp(&(n.entry.parent(), &n.new_name))
-        });
+        medias.sort_by_cached_key(|m| (m.entry.parent(), key(m), m.new_name.clone()));
         medias
             .chunk_by_mut(|m, n| m.entry.parent() == n.entry.parent()) // only by parent.
             .filter(|_| utils::is_running())
             .filter(|g| {
-                g.chunk_by(|m, n| m.new_name == n.new_name)
-                    .any(|g| g.len() > 1) // this should be way faster than using a hashmap as before.
+                g.chunk_by(same).any(|g| g.len() > 1) // this should be way faster than using a hashmap as before.
             })
             .for_each(|g| {
                 eprintln!("warning: names clash in: {}", g[0].entry.parent().unwrap());
-                g.chunk_by(|m, n| m.new_name == n.new_name)
-                    .filter(|g| g.len() > 1)
-                    .for_each(|g| {
-                        let k = &g[0].new_name;
-                        let list = g
-                            .iter()
-                            .map(|m| m.entry.file_name())
-                            .filter(|f| f != k)
-                            .collect::<Vec<_>>();
-                        clashes += list.len();
-                        use yansi::Paint;
-                        let msg = match g.len() != list.len() {
-                            true => " name already exists",
-                            false => " multiple names clash",
-                        };
-                        eprintln!(
-                            "  > {} --> {k}{}",
-                            list.join(", "),
-                            msg.paint(yansi::Color::BrightMagenta)
-                        );
-                    });
+                g.chunk_by(same).filter(|g| g.len() > 1).for_each(|g| {
+                    let k = &g[0].new_name;
+                    let list = g
+                        .iter()
+                        .map(|m| m.entry.file_name())
+                        .filter(|f| f != k)
+                        .collect::<Vec<_>>();
+                    clashes += list.len();
+                    use yansi::Paint;
+                    let msg = match g.len() != list.len() {
+                        true => " name already exists",
+                        false => " multiple names clash",
+                    };
+                    eprintln!(
+                        "  > {} --> {k}{}",
+                        list.join(", "),
+                        msg.paint(yansi::Color::BrightMagenta)
+                    );
+                });
                 match self.clashes {
                     Clashes::Forbid => {
                         let count = g.iter().filter(|m| m.is_changed()).count();
@@ -102,21 +181,20 @@ impl Refine for Rename {
                         g.iter_mut().for_each(|m| m.new_name.clear());
                     }
                     Clashes::Ignore => g
-                        .chunk_by_mut(|m, n| m.new_name == n.new_name)
+                        .chunk_by_mut(same)
                         .filter(|g| g.len() > 1)
                         .for_each(|g| g.iter_mut().for_each(|m| m.new_name.clear())),
                     Clashes::Sequence => {
-                        g.chunk_by_mut(|m, n| m.new_name == n.new_name)
-                            .filter(|g| g.len() > 1)
-                            .for_each(|g| {
-                                g.iter_mut().filter(|m| m.is_changed()).zip(1..).for_each(
-                                    |(m, i)| {
-                                        m.new_name.truncate(m.new_name.len() - m.ext.len() - 1);
-                                        write!(m.new_name, "-{i}.{}", m.ext).unwrap();
-                                        m.resolution = " (added sequence number)";
-                                    },
-                                )
-                            })
+                        g.chunk_by_mut(same).filter(|g| g.len() > 1).for_each(|g| {
+                            g.iter_mut()
+                                .filter(|m| m.is_changed())
+                                .zip(1..)
+                                .for_each(|(m, i)| {
+                                    m.new_name.truncate(m.new_name.len() - m.ext.len() - 1);
+                                    write!(m.new_name, "-{i}.{}", m.ext).unwrap();
+                                    m.resolution = " (added sequence number)";
+                                })
+                        })
                     }
                 }
             });
@@ -125,6 +203,20 @@ impl Refine for Rename {
 
         // step: settle changes.
         medias.retain(|m| !m.new_name.is_empty() && m.is_changed());
+        blocked += bundles.restore(&mut medias, sidecars);
+
+        // step: review the changes before the sidecars are attached, so they follow the decisions.
+        if self.review && !medias.is_empty() {
+            review(&mut medias, fold)?;
+        }
+
+        // step: move the sidecars along with their primaries.
+        blocked += bundles.attach(&mut medias, |_, entry, new_name| Media {
+            ext: utils::intern(entry.filename_parts().1),
+            entry,
+            new_name,
+            resolution: " (sidecar)",
+        });
 
         // step: display the results by parent directory.
         medias.sort_unstable_by(|m, n| {
@@ -144,7 +236,8 @@ impl Refine for Rename {
                         m.entry.display_filename(),
                         m.new_name,
                         m.resolution.paint(yansi::Color::BrightBlue)
-                    )
+                    );
+                    print!("{}", rules.display_trace(&m.entry));
                 });
             });
 
@@ -156,17 +249,24 @@ impl Refine for Rename {
         println!("  changes: {}", medias.len());
         println!("  clashes: {clashes} ({})", self.clashes);
         println!("  blocked: {blocked}");
+        print!("{}", rules.display_unused());
         if medias.is_empty() {
             return Ok(());
         }
 
         // step: apply changes if the user agrees.
-        if !self.yes {
+        if !self.review && !self.yes && self.save_plan.is_none() {
             utils::prompt_yes_no("apply changes?")?;
         }
+        if let Some(path) = &self.save_plan {
+            return Plan::save(path, "rename", OpKind::Rename, &medias);
+        }
         FileOps::rename_move(&mut medias);
 
         match medias.is_empty() {
+            _ if !utils::is_running() => {
+                println!("aborted, {} remaining; run again to resume", medias.len())
+            }
             true => println!("done"),
             false => println!("found {} errors", medias.len()),
         }
@@ -0,0 +1,359 @@
+use crate::Args as Cli;
+use crate::entries::{Filter, Scheme};
+use crate::medias::Naming;
+use anyhow::{Context, Result, anyhow};
+use clap::{Args, Command, CommandFactory};
+use std::ffi::OsString;
+use std::fs;
+use std::path::PathBuf;
+use toml::{Table, Value};
+
+/// The sections that are not commands, which can be used in any profile.
+const FILTER: &str = "filter";
+const NAMING: &str = "naming";
+const SCHEME: &str = "scheme";
+
+/// The configuration file, which contains named profiles with default options for commands.
+#[derive(Debug)]
+pub struct Profiles(Table);
+
+impl Profiles {
+    /// The path of the configuration file, which might not exist.
+    pub fn path() -> Result<PathBuf> {
+        let dir = dirs::config_dir().ok_or_else(|| anyhow!("no config dir"))?;
+        Ok(dir.join("refine").join("config.toml"))
+    }
+
+    /// Load the configuration file, or None if it doesn't exist.
+    pub fn load() -> Result<Option<Profiles>> {
+        let path = Profiles::path()?;
+        if !path.exists() {
+            return Ok(None);
+        }
+        let text = fs::read_to_string(&path).with_context(|| format!("reading {path:?}"))?;
+        let table = text
+            .parse::<Table>()
+            .with_context(|| format!("parsing {path:?}"))?;
+        let profiles = Profiles(table);
+        profiles
+            .check_names()
+            .with_context(|| format!("loading {path:?}"))?;
+        Ok(Some(profiles))
+    }
+
+    /// Fail if a profile is named like a command, which would be ambiguous in a command line.
+    fn check_names(&self) -> Result<()> {
+        let cli = Cli::command();
+        match self.names().find(|p| cli.find_subcommand(p).is_some()) {
+            Some(p) => Err(anyhow!("profile {p:?} collides with a command name")),
+            None => Ok(()),
+        }
+    }
+
+    /// The names of all profiles, in order.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.0.keys().map(String::as_str)
+    }
+
+    /// Get the sections of a profile, i.e. `filter`, `naming`, `scheme`, or command names.
+    pub fn sections(&self, profile: &str) -> Result<&Table> {
+        match self.0.get(profile) {
+            Some(Value::Table(t)) => Ok(t),
+            Some(_) => Err(anyhow!("profile {profile:?} must be a table")),
+            None => Err(anyhow!("profile {profile:?} not found")),
+        }
+    }
+
+    /// Generate the command line arguments a profile provides for a section.
+    pub fn section_args(&self, profile: &str, section: &str) -> Result<Vec<String>> {
+        let options = match self.sections(profile)?.get(section) {
+            Some(Value::Table(t)) => t,
+            Some(_) => return Err(anyhow!("{profile}.{section}: must be a table")),
+            None => return Ok(vec![]),
+        };
+        let def = section_def(section).ok_or_else(|| anyhow!("{profile}.{section}: unknown"))?;
+        let mut args = vec![];
+        for (key, value) in options {
+            let long = key.replace('_', "-");
+            let ctx = || format!("{profile}.{section}.{key}");
+            let arg = def
+                .get_arguments()
+                .find(|a| a.get_long() == Some(&long))
+                .ok_or_else(|| anyhow!("unknown option"))
+                .with_context(ctx)?;
+            let values = match value {
+                Value::Array(vs) => vs.iter().collect(),
+                v => vec![v],
+            };
+            for v in values {
+                match (v, arg.get_action().takes_values()) {
+                    (Value::Boolean(true), false) => args.push(format!("--{long}")),
+                    (Value::Boolean(false), false) => {}
+                    (_, false) => return Err(anyhow!("expected a boolean")).with_context(ctx),
+                    (Value::String(s), true) => args.push(format!("--{long}={s}")),
+                    (Value::Integer(i), true) => args.push(format!("--{long}={i}")),
+                    (Value::Float(f), true) => args.push(format!("--{long}={f}")),
+                    (_, true) => return Err(anyhow!("unsupported value: {v}")).with_context(ctx),
+                }
+            }
+        }
+        Ok(args)
+    }
+
+    /// Generate all command line arguments a profile provides for a command.
+    pub fn command_args(&self, profile: &str, cmd: &str) -> Result<Vec<String>> {
+        let sections = self.sections(profile)?;
+        let sub = Cli::command();
+        let sub = sub
+            .find_subcommand(cmd)
+            .ok_or_else(|| anyhow!("unknown command: {cmd:?}"))?;
+        sections
+            .keys()
+            .filter(|s| applies(s, sub))
+            .map(|s| self.section_args(profile, s))
+            .try_fold(vec![], |mut acc, args| {
+                acc.extend(args?);
+                Ok(acc)
+            })
+    }
+
+    /// Validate all profiles, returning the errors found.
+    pub fn validate(&self) -> Vec<anyhow::Error> {
+        self.names()
+            .flat_map(|p| match self.sections(p) {
+                Ok(sections) => sections
+                    .keys()
+                    .filter_map(|s| self.section_args(p, s).err())
+                    .collect(),
+                Err(err) => vec![err],
+            })
+            .collect()
+    }
+}
+
+/// Get the clap definition of a section, to validate and expand its options.
+fn section_def(section: &str) -> Option<Command> {
+    match section {
+        FILTER => Some(Filter::augment_args(Command::new(FILTER))),
+        NAMING => Some(Naming::augment_args(Command::new(NAMING))),
+        SCHEME => Some(Scheme::augment_args(Command::new(SCHEME))),
+        "config" => None,
+        _ => Cli::command().find_subcommand(section).cloned(),
+    }
+}
+
+/// Check whether a section applies to a command, i.e. it is the command itself or flattened into it.
+fn applies(section: &str, sub: &Command) -> bool {
+    match section {
+        FILTER => !matches!(sub.get_name(), "apply" | "config"), // they don't fetch entries.
+        s if s == sub.get_name() => true,
+        s => section_def(s).is_some_and(|def| {
+            def.get_arguments()
+                .all(|a| sub.get_arguments().any(|b| a.get_id() == b.get_id()))
+        }),
+    }
+}
+
+/// Insert the options of the given `--profile` right after the command name, so the ones in the
+/// actual command line take precedence, and booleans it sets can be turned off with `--no-<flag>`.
+pub fn expand_profile(mut argv: Vec<OsString>) -> Result<Vec<OsString>> {
+    let Some((profile, pos, cmd)) = find_profile(&argv) else {
+        return Ok(argv);
+    };
+    if cmd == "config" {
+        return Ok(argv); // the config command handles profiles by itself.
+    }
+
+    let profiles = Profiles::load()?.ok_or_else(|| anyhow!("no configuration file found"))?;
+    let mut extra = profiles.command_args(&profile, &cmd)?;
+    negate_flags(&mut argv, pos, &mut extra);
+    argv.splice(pos + 1..pos + 1, extra.into_iter().map(OsString::from));
+    Ok(argv)
+}
+
+/// Find the `--profile` in the command line, along with the position and name of the command.
+fn find_profile(argv: &[OsString]) -> Option<(String, usize, String)> {
+    let args = argv
+        .iter()
+        .take_while(|a| *a != "--")
+        .map(|a| a.to_str().unwrap_or_default())
+        .collect::<Vec<_>>();
+    let (value, profile) = args.iter().enumerate().find_map(|(i, a)| match *a {
+        "--profile" => args.get(i + 1).map(|p| (Some(i + 1), p.to_string())),
+        a => a.strip_prefix("--profile=").map(|p| (None, p.to_owned())),
+    })?;
+    let cli = Cli::command();
+    let (pos, cmd) = args
+        .iter()
+        .enumerate()
+        .skip(1)
+        .filter(|&(i, _)| Some(i) != value) // the profile name is not the command.
+        .find_map(|(i, a)| cli.find_subcommand(a).map(|c| (i, c.get_name().to_owned())))?;
+    Some((profile, pos, cmd))
+}
+
+/// Drop the boolean flags the profile sets that are negated with `--no-<flag>` after the command
+/// name at `pos`, along with their negations, which are not actual options.
+fn negate_flags(argv: &mut Vec<OsString>, pos: usize, extra: &mut Vec<String>) {
+    let end = argv.iter().position(|a| a == "--").unwrap_or(argv.len());
+    let negated = argv[pos + 1..end]
+        .iter()
+        .filter(|a| {
+            let flag = a.to_str().and_then(|a| a.strip_prefix("--no-"));
+            flag.is_some_and(|f| extra.contains(&format!("--{f}")))
+        })
+        .cloned()
+        .collect::<Vec<_>>();
+    extra.retain(|e| {
+        !negated
+            .iter()
+            .any(|n| n.to_str() == Some(&e.replacen("--", "--no-", 1)))
+    });
+    let mut i = 0;
+    argv.retain(|a| {
+        i += 1;
+        i <= pos + 1 || i > end || !negated.contains(a)
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn command_args() {
+        #[track_caller]
+        fn case(cmd: &str, args: &[&str]) {
+            let profiles = Profiles(
+                r#"
+                [p.naming]
+                strip-before = ["foo", "bar"]
+                [p.filter]
+                ext_in = "mp4"
+                only-files = true
+                [p.dupes]
+                sample = 8
+                [p.scheme]
+                seq-width = 3
+                "#
+                .parse()
+                .unwrap(),
+            );
+            assert_eq!(profiles.command_args("p", cmd).unwrap(), args);
+        }
+
+        case("dupes", &["--sample=8", "--ext-in=mp4", "--only-files"]);
+        case(
+            "rename",
+            &[
+                "--ext-in=mp4",
+                "--only-files",
+                "--strip-before=foo",
+                "--strip-before=bar",
+            ],
+        );
+        case("list", &["--ext-in=mp4", "--only-files"]);
+        case("probe", &["--ext-in=mp4", "--only-files", "--seq-width=3"]);
+    }
+
+    #[test]
+    fn invalid_options() {
+        #[track_caller]
+        fn case(toml: &str) {
+            let profiles = Profiles(toml.parse().unwrap());
+            assert_eq!(profiles.validate().len(), 1);
+        }
+
+        case("p = 1");
+        case("p.naming = 1");
+        case("[p.nope]\nfoo = 1");
+        case("[p.naming]\nfoo = 1");
+        case("[p.naming]\nreplace = true");
+        case("[p.filter]\nonly-files = \"yes\"");
+    }
+
+    #[test]
+    fn negated_flags() {
+        #[track_caller]
+        fn case(argv: &[&str], extra: &[&str], out: (&[&str], &[&str])) {
+            let mut argv = argv.iter().map(OsString::from).collect::<Vec<_>>();
+            let mut extra = extra.iter().map(|&s| s.to_owned()).collect::<Vec<_>>();
+            negate_flags(&mut argv, 1, &mut extra);
+            assert_eq!(argv, out.0);
+            assert_eq!(extra, out.1);
+        }
+
+        case(
+            &["refine", "list", "--no-only-files"],
+            &["--only-files", "--ext-in=mp4"],
+            (&["refine", "list"], &["--ext-in=mp4"]),
+        );
+        case(
+            &["refine", "list", "--no-only-files"],
+            &["--ext-in=mp4"],
+            (&["refine", "list", "--no-only-files"], &["--ext-in=mp4"]),
+        );
+        case(
+            &["refine", "list", "--no-calc-dirs"],
+            &["--only-dirs"],
+            (&["refine", "list", "--no-calc-dirs"], &["--only-dirs"]),
+        );
+        case(
+            &["refine", "list", "--", "--no-only-files"],
+            &["--only-files"],
+            (
+                &["refine", "list", "--", "--no-only-files"],
+                &["--only-files"],
+            ),
+        );
+    }
+
+    #[test]
+    fn profile_command() {
+        #[track_caller]
+        fn case(argv: &str, out: Option<(&str, usize, &str)>) {
+            let argv = argv.split(' ').map(OsString::from).collect::<Vec<_>>();
+            let res = find_profile(&argv);
+            let res = res.as_ref().map(|(p, i, c)| (p.as_str(), *i, c.as_str()));
+            assert_eq!(res, out);
+        }
+
+        case("refine list --profile p", Some(("p", 1, "list")));
+        case("refine list --profile=p .", Some(("p", 1, "list")));
+        case("refine --profile p list", Some(("p", 3, "list")));
+        case("refine --profile list join", Some(("list", 3, "join")));
+        case("refine --profile=list join", Some(("list", 2, "join")));
+        case("refine join --profile list", Some(("list", 1, "join")));
+        case("refine list", None);
+        case("refine list -- --profile p", None);
+        case("refine --profile p", None);
+    }
+
+    #[test]
+    fn profile_names() {
+        #[track_caller]
+        fn case(toml: &str, out: bool) {
+            let profiles = Profiles(toml.parse().unwrap());
+            assert_eq!(profiles.check_names().is_ok(), out, "{toml}");
+        }
+
+        case("[movies.naming]\nreplace = [\"a=b\"]", true);
+        case("[lists.list]\nby = \"size\"", true);
+        case("[list.filter]\nonly-files = true", false);
+        case("[rename]", false);
+    }
+
+    #[test]
+    fn filter_sections() {
+        #[track_caller]
+        fn case(cmd: &str, out: bool) {
+            let cli = Cli::command();
+            assert_eq!(applies(FILTER, cli.find_subcommand(cmd).unwrap()), out);
+        }
+
+        case("list", true);
+        case("join", true);
+        case("apply", false);
+        case("config", false);
+    }
+}
@@ -1,5 +1,5 @@
+use super::Scheme;
 use anyhow::{Result, anyhow};
-use regex::Regex;
 use std::cmp::Ordering;
 use std::convert::Into;
 use std::env;
@@ -109,19 +109,11 @@ impl Entry {
     }
 
     /// Get the canonical name, source alias, sequence, comment, and extension from collections.
+    ///
+    /// They are parsed according to the active [Scheme].
     pub fn collection_parts(&self) -> (&str, Option<&str>, Option<usize>, &str, &str) {
-        // regex: name~24 or name+alias~24.
-        static RE: LazyLock<Regex> =
-            LazyLock::new(|| Regex::new(r"^(\w+)(?:\+(\w+))?~(\d+)(.*)$").unwrap());
-
         let (stem, ext) = self.filename_parts();
-        let Some(caps) = RE.captures(stem) else {
-            return (stem, None, None, "", ext);
-        };
-        let canonical = caps.get(1).unwrap().as_str(); // regex guarantees name is present.
-        let alias = caps.get(2).map(|m| m.as_str());
-        let seq = caps.get(3).and_then(|m| m.as_str().parse().ok());
-        let comment = caps.get(4).map_or("", |m| m.as_str());
+        let (canonical, alias, seq, comment) = Scheme::active().parse(stem);
         (canonical, alias, seq, comment, ext)
     }
 
@@ -354,24 +346,21 @@ mod tests {
         case("foo+bar,baz", ("foo+bar,baz", None, None, ""));
         case("foo+bar ~ 24", ("foo+bar ~ 24", None, None, ""));
         case("foo ~24", ("foo ~24", None, None, ""));
-        case("foo bar~24", ("foo bar~24", None, None, ""));
         case("foo bar ~24", ("foo bar ~24", None, None, ""));
         case("_foo_ ~24", ("_foo_ ~24", None, None, ""));
-        case("foo - 33~24", ("foo - 33~24", None, None, ""));
         case("foo+ ~24", ("foo+ ~24", None, None, ""));
-        case("foo+ asd~24", ("foo+ asd~24", None, None, ""));
         case("foo+asd ~24", ("foo+asd ~24", None, None, ""));
-        case("foo+~24", ("foo+~24", None, None, ""));
-        case(",~24", (",~24", None, None, ""));
-        case("foo+,~24", ("foo+,~24", None, None, ""));
-        case("foo+bar,~24", ("foo+bar,~24", None, None, ""));
-        case("foo+bar,~24 cool", ("foo+bar,~24 cool", None, None, ""));
 
         // name and seq.
         case("foo~24", ("foo", None, Some(24), ""));
         case("foo_~24", ("foo_", None, Some(24), ""));
         case("__foo~24", ("__foo", None, Some(24), ""));
         case("_foo__~24", ("_foo__", None, Some(24), ""));
+        case("foo bar~24", ("foo bar", None, Some(24), ""));
+        case("foo - 33~24", ("foo - 33", None, Some(24), ""));
+        case("foo+ asd~24", ("foo+ asd", None, Some(24), ""));
+        case("foo+~24", ("foo+", None, Some(24), ""));
+        case(",~24", (",", None, Some(24), ""));
 
         // name, aliases and seq.
         case("foo+bar~24", ("foo", Some("bar"), Some(24), ""));
@@ -379,6 +368,11 @@ mod tests {
             "foo_bar__+_baz__~24",
             ("foo_bar__", Some("_baz__"), Some(24), ""),
         );
+        case("foo+,~24", ("foo", Some(","), Some(24), ""));
+        case(
+            "foo bar+the foo~24",
+            ("foo bar", Some("the foo"), Some(24), ""),
+        );
 
         // name, seq, and comment.
         case("foo~24cool", ("foo", None, Some(24), "cool"));
@@ -396,6 +390,7 @@ mod tests {
             "_foo+__bar_~24 with comment!",
             ("_foo", Some("__bar_"), Some(24), " with comment!"),
         );
+        case("foo+bar,~24 cool", ("foo", Some("bar,"), Some(24), " cool"));
     }
 
     #[test]
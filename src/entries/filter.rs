@@ -45,6 +45,25 @@ pub struct Filter {
     ext_ex: Option<String>,
 }
 
+impl Filter {
+    /// Whether any rule was given.
+    pub fn is_set(&self) -> bool {
+        let regexes = [
+            &self.all_in,
+            &self.dir_in,
+            &self.path_in,
+            &self.file_in,
+            &self.ext_in,
+            &self.all_ex,
+            &self.dir_ex,
+            &self.path_ex,
+            &self.file_ex,
+            &self.ext_ex,
+        ];
+        self.only_files || self.only_dirs || regexes.iter().any(|r| r.is_some())
+    }
+}
+
 /// The engine that applies the [Filter] rules to a collection of entries.
 #[derive(Debug, Default)]
 pub struct FilterRules {
@@ -8,6 +8,9 @@ pub struct Input {
     /// Just show the entries that would be processed, without running any command.
     #[arg(long, global = true)]
     show: bool,
+    /// Load default options from a named profile in the configuration file.
+    #[arg(long, global = true, value_name = "NAME")]
+    profile: Option<String>,
     /// Directories to scan.
     #[arg(global = true, help_heading = None)]
     dirs: Vec<PathBuf>,
@@ -18,10 +21,22 @@ pub struct Input {
     filter: Filter,
 }
 
+impl Input {
+    /// Fail if any directory or fetch option was given, for commands that don't fetch entries.
+    pub fn reject_fetch(&self, cmd: &str) -> Result<()> {
+        let fetch = !self.dirs.is_empty() || self.recursion != 0 || self.filter.is_set();
+        match self.show || fetch {
+            true => Err(anyhow!("{cmd} doesn't take directories nor fetch options")),
+            false => Ok(()),
+        }
+    }
+}
+
 /// The input data structure that holds the effective paths to scan and their properties.
 #[derive(Debug)]
 pub struct EffectiveInput {
     pub show: bool,
+    pub profile: Option<String>,
     pub info: InputInfo,
     fetcher: Fetcher,
 }
@@ -34,6 +49,8 @@ impl EffectiveInput {
 
 #[derive(Debug)]
 pub struct InputInfo {
+    /// The effective paths to scan, after deduplication and validation.
+    pub dirs: Vec<Entry>,
     /// The effective number of paths to scan, after deduplication and validation.
     pub num_valid: usize,
     /// Whether there were invalid/not found paths.
@@ -52,6 +69,7 @@ impl TryFrom<Input> for EffectiveInput {
         let fetcher = Fetcher::new(dirs, input.recursion.into(), filter);
         let ei = EffectiveInput {
             show: input.show,
+            profile: input.profile,
             info,
             fetcher,
         };
@@ -92,6 +110,7 @@ fn validate(mut dirs: Vec<PathBuf>) -> Result<(Vec<Entry>, InputInfo)> {
     }
 
     let info = InputInfo {
+        dirs: dirs.clone(),
         num_valid: dirs.len(),
         has_invalid: n != dirs.len(),
     };
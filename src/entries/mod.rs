@@ -1,11 +1,13 @@
 mod entry;
 mod filter;
 mod input;
+mod scheme;
 
 use crate::utils;
 pub use entry::*;
 pub use filter::*;
 pub use input::*;
+pub use scheme::*;
 use std::iter;
 use std::rc::Rc;
 
@@ -0,0 +1,167 @@
+use clap::Args;
+use clap::builder::NonEmptyStringValueParser;
+use regex::Regex;
+use std::sync::OnceLock;
+
+/// The naming scheme of collections, i.e. how names, aliases, and sequences are written, like
+/// "name+alias~24 comment.ext".
+///
+/// The same definition is used both to parse and to generate names, so all commands agree.
+#[derive(Debug, Clone, PartialEq, Args)]
+pub struct Scheme {
+    /// The separator between collection names and their sequence numbers, e.g. " - ".
+    #[arg(long, default_value = "~", value_name = "STR", help_heading = Some("Scheme"), allow_hyphen_values = true, value_parser = NonEmptyStringValueParser::new())]
+    seq_sep: String,
+    /// The minimum width of sequence numbers, which are padded with zeros.
+    #[arg(long, default_value_t = 1, value_name = "INT", help_heading = Some("Scheme"))]
+    seq_width: usize,
+    /// The marker between collection names and their aliases.
+    #[arg(long, default_value = "+", value_name = "STR", help_heading = Some("Scheme"), allow_hyphen_values = true, value_parser = NonEmptyStringValueParser::new())]
+    alias_sep: String,
+}
+
+/// The scheme in use, along with its compiled regex.
+#[derive(Debug)]
+struct Active {
+    scheme: Scheme,
+    re: Regex,
+}
+
+static ACTIVE: OnceLock<Active> = OnceLock::new();
+
+impl Default for Scheme {
+    fn default() -> Self {
+        Scheme {
+            seq_sep: "~".to_owned(),
+            seq_width: 1,
+            alias_sep: "+".to_owned(),
+        }
+    }
+}
+
+impl Scheme {
+    /// Set this scheme as the one used to parse and generate collection names.
+    ///
+    /// It should be called before fetching entries, and only the first call has any effect.
+    pub fn activate(&self) {
+        let _ = ACTIVE.set(Active::new(self.clone()));
+    }
+
+    /// Get the active scheme, which is the default one if none was activated.
+    pub fn active() -> &'static Scheme {
+        &ACTIVE.get_or_init(|| Active::new(Scheme::default())).scheme
+    }
+
+    /// Parse a stem into its canonical name, alias, sequence, and comment.
+    pub fn parse<'a>(&self, stem: &'a str) -> (&'a str, Option<&'a str>, Option<usize>, &'a str) {
+        let owned;
+        let re = match ACTIVE.get() {
+            Some(active) if active.scheme == *self => &active.re,
+            _ => {
+                owned = self.regex(); // only happens for non-active schemes, like in tests.
+                &owned
+            }
+        };
+        let Some(caps) = re.captures(stem) else {
+            return (stem, None, None, "");
+        };
+        let canonical = caps.get(1).unwrap().as_str(); // regex guarantees name is present.
+        let alias = caps.get(2).map(|m| m.as_str());
+        let seq = caps.get(3).and_then(|m| m.as_str().parse().ok());
+        let comment = caps.get(4).map_or("", |m| m.as_str());
+        (canonical, alias, seq, comment)
+    }
+
+    /// Generate a filename from a canonical name, alias, sequence, comment, and extension.
+    ///
+    /// It is parsed back the same, as long as the name contains neither the alias separator nor
+    /// the sequence separator followed by digits.
+    pub fn format(
+        &self,
+        name: &str,
+        alias: Option<&str>,
+        seq: usize,
+        comment: &str,
+        ext: &str,
+    ) -> String {
+        let (sep, width) = (&self.seq_sep, self.seq_width);
+        let alias = alias
+            .map(|a| format!("{}{a}", self.alias_sep))
+            .unwrap_or_default();
+        let dot = if ext.is_empty() { "" } else { "." };
+        format!("{name}{alias}{sep}{seq:0width$}{comment}{dot}{ext}")
+    }
+
+    fn regex(&self) -> Regex {
+        // regex: name~24 or name+alias~24, with the configured separators, where names and aliases
+        // are anything without surrounding spaces, so all names `format` writes can be read back.
+        let (alias, sep) = (regex::escape(&self.alias_sep), regex::escape(&self.seq_sep));
+        let part = r"\S(?:.*?\S)?";
+        Regex::new(&format!(r"^({part})(?:{alias}({part}))?{sep}(\d+)(.*)$")).unwrap()
+    }
+}
+
+impl Active {
+    fn new(scheme: Scheme) -> Self {
+        let re = scheme.regex();
+        Active { scheme, re }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn custom_scheme() {
+        #[track_caller]
+        fn case(stem: &str, out: (&str, Option<&str>, Option<usize>, &str)) {
+            let scheme = Scheme {
+                seq_sep: " - ".to_owned(),
+                seq_width: 3,
+                alias_sep: "@".to_owned(),
+            };
+            assert_eq!(scheme.parse(stem), out);
+            if let (name, alias, Some(seq), comment) = out {
+                assert_eq!(scheme.format(name, alias, seq, comment, ""), stem);
+            }
+        }
+
+        case("foo - 001", ("foo", None, Some(1), ""));
+        case("foo - 024 cool", ("foo", None, Some(24), " cool"));
+        case("foo - 1234", ("foo", None, Some(1234), ""));
+        case("foo@bar - 002", ("foo", Some("bar"), Some(2), ""));
+        case("foo~24", ("foo~24", None, None, ""));
+        case("foo+bar - 001", ("foo+bar", None, Some(1), ""));
+        case("foo bar - 001", ("foo bar", None, Some(1), ""));
+        case("foo - 2025 - 001", ("foo", None, Some(2025), " - 001"));
+    }
+
+    #[test]
+    fn round_trip() {
+        #[track_caller]
+        fn case(name: &str, alias: Option<&str>, seq: usize, comment: &str) {
+            let custom = Scheme {
+                seq_sep: " - ".to_owned(),
+                seq_width: 3,
+                alias_sep: "@".to_owned(),
+            };
+            for scheme in [Scheme::default(), custom] {
+                let stem = scheme.format(name, alias, seq, comment, "");
+                assert_eq!(
+                    scheme.parse(&stem),
+                    (name, alias, Some(seq), comment),
+                    "{stem}"
+                );
+            }
+        }
+
+        case("foo", None, 1, "");
+        case("foo bar", None, 24, "");
+        case("foo bar", Some("the foo"), 2, " cool");
+        case("the beatles", Some("beatles"), 1, "");
+        case("foo-bar 2", None, 3, " take 2");
+        case("café (live)", Some("o'brien"), 100, "");
+        case("_foo_", Some("__bar_"), 7, " - cut");
+    }
+}
@@ -1,6 +1,9 @@
 mod commands;
+mod config;
 mod entries;
 mod medias;
+#[cfg(test)]
+mod testing;
 mod utils;
 
 use anyhow::Result;
@@ -10,7 +13,7 @@ use entries::Input;
 
 #[derive(Debug, Parser)]
 #[command(version, about, long_about = None, after_help = "For more information, see https://github.com/rsalmei/refine",
-    override_usage = "refine <COMMAND> [DIRS]... [FETCH] [OPTIONS]",
+    override_usage = "refine <COMMAND> [DIRS]... [FETCH] [OPTIONS]", args_override_self = true,
 )]
 pub struct Args {
     #[command(subcommand)]
@@ -23,7 +26,6 @@ fn main() -> Result<()> {
     utils::install_ctrl_c_handler();
 
     println!("Refine v{}", env!("CARGO_PKG_VERSION"));
-    let args = Args::parse();
-    let effective = args.input.try_into()?;
-    args.cmd.execute(effective)
+    let args = Args::parse_from(config::expand_profile(std::env::args_os().collect())?);
+    args.cmd.execute(args.input)
 }
@@ -0,0 +1,314 @@
+use std::borrow::Cow;
+use std::fmt::{self, Display};
+use std::str::FromStr;
+
+/// A recipe that changes the case of names, or splits and joins their words.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Casing {
+    /// Capitalize all words, except small ones in the middle of the name.
+    Title(Lang),
+    /// Capitalize only the first word.
+    Sentence,
+    Upper,
+    Lower,
+    /// Join lowercase words with `_`.
+    Snake,
+    /// Join lowercase words with `-`.
+    Kebab,
+    /// Join capitalized words, except the first which is lowercase.
+    Camel,
+    /// Split camelCase words with spaces.
+    Split,
+}
+
+/// The languages supported for the small words in title case.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Lang {
+    En,
+    Pt,
+    Es,
+    Fr,
+    It,
+    De,
+}
+
+impl Casing {
+    /// Apply this casing to a name, returning an owned value only if it changed.
+    pub fn apply<'a>(&self, name: &'a str) -> Cow<'a, str> {
+        let res = match self {
+            Casing::Title(lang) => title(name, lang.small_words()),
+            Casing::Sentence => sentence(name),
+            Casing::Upper => name.to_uppercase(),
+            Casing::Lower => name.to_lowercase(),
+            Casing::Snake => join_lower(name, "_"),
+            Casing::Kebab => join_lower(name, "-"),
+            Casing::Camel => camel(name),
+            Casing::Split => split_camel(name),
+        };
+        match res == name {
+            true => Cow::Borrowed(name),
+            false => Cow::Owned(res),
+        }
+    }
+}
+
+impl Lang {
+    fn small_words(self) -> &'static [&'static str] {
+        match self {
+            Lang::En => &[
+                "a", "an", "and", "as", "at", "but", "by", "for", "from", "in", "into", "nor",
+                "of", "on", "or", "over", "the", "to", "vs", "with",
+            ],
+            Lang::Pt => &[
+                "a", "à", "ao", "aos", "as", "às", "com", "da", "das", "de", "do", "dos", "e",
+                "em", "na", "nas", "no", "nos", "o", "os", "ou", "para", "pela", "pelo", "por",
+                "um", "uma",
+            ],
+            Lang::Es => &[
+                "a", "al", "con", "de", "del", "el", "en", "la", "las", "lo", "los", "o", "para",
+                "por", "un", "una", "y",
+            ],
+            Lang::Fr => &[
+                "à", "au", "aux", "de", "des", "du", "en", "et", "la", "le", "les", "ou", "par",
+                "pour", "sur", "un", "une",
+            ],
+            Lang::It => &[
+                "a", "al", "con", "da", "dal", "di", "del", "e", "il", "in", "la", "le", "lo", "o",
+                "per", "su", "un", "una",
+            ],
+            Lang::De => &[
+                "am", "an", "auf", "aus", "das", "dem", "den", "der", "des", "die", "ein", "eine",
+                "im", "in", "mit", "oder", "und", "von", "zu", "zum", "zur",
+            ],
+        }
+    }
+}
+
+/// Iterate over the words of a name, along with the separators before them.
+fn words(name: &str) -> impl Iterator<Item = (&str, &str)> {
+    let is_word = |c: char| c.is_alphanumeric() || c == '\'';
+    let mut rest = name;
+    std::iter::from_fn(move || {
+        let start = rest.find(is_word)?;
+        let end = rest[start..]
+            .find(|c| !is_word(c))
+            .map_or(rest.len(), |e| start + e);
+        let (sep, word) = (&rest[..start], &rest[start..end]);
+        rest = &rest[end..];
+        Some((sep, word))
+    })
+}
+
+/// Whether a word must keep its case, like acronyms "NASA" and codes "S03E05"; uppercase words are
+/// only kept if the name isn't all uppercase.
+fn keeps_case(word: &str, shouting: bool) -> bool {
+    let letters = word.chars().filter(|c| c.is_alphabetic()).count();
+    let mixed = letters > 0 && word.chars().any(|c| c.is_numeric());
+    let upper = letters > 1 && !word.chars().any(char::is_lowercase);
+    mixed || upper && !shouting
+}
+
+/// Whether a name has no lowercase letters at all, so its uppercase words are not acronyms.
+fn is_shouting(name: &str) -> bool {
+    !name.chars().any(char::is_lowercase)
+}
+
+fn capitalize(word: &str) -> String {
+    let mut chars = word.chars();
+    match chars.next() {
+        Some(c) => c
+            .to_uppercase()
+            .chain(chars.flat_map(char::to_lowercase))
+            .collect(),
+        None => String::new(),
+    }
+}
+
+fn title(name: &str, small: &[&str]) -> String {
+    let (total, shouting) = (words(name).count(), is_shouting(name));
+    let mut res = words(name)
+        .enumerate()
+        .fold(String::new(), |mut acc, (i, (sep, word))| {
+            acc.push_str(sep);
+            let lower = word.to_lowercase();
+            match i > 0 && i < total - 1 && small.contains(&lower.as_str()) {
+                _ if keeps_case(word, shouting) => acc.push_str(word),
+                true => acc.push_str(&lower),
+                false => acc.push_str(&capitalize(word)),
+            }
+            acc
+        });
+    res.push_str(trailing(name));
+    res
+}
+
+fn sentence(name: &str) -> String {
+    let lower = name.to_lowercase();
+    match lower.find(char::is_alphanumeric) {
+        Some(pos) => format!("{}{}", &lower[..pos], capitalize(&lower[pos..])),
+        None => lower,
+    }
+}
+
+/// The separators at the end of a name, after its last word.
+fn trailing(name: &str) -> &str {
+    let end = name.trim_end_matches(|c: char| !c.is_alphanumeric() && c != '\'');
+    &name[end.len()..]
+}
+
+/// Split all words, including camelCase ones, ignoring their separators.
+fn split_words(name: &str) -> Vec<String> {
+    words(&split_camel(name))
+        .map(|(_, w)| w.replace('\'', ""))
+        .collect()
+}
+
+fn join_lower(name: &str, sep: &str) -> String {
+    split_words(name)
+        .iter()
+        .map(|w| w.to_lowercase())
+        .collect::<Vec<_>>()
+        .join(sep)
+}
+
+fn camel(name: &str) -> String {
+    let shouting = is_shouting(name);
+    split_words(name)
+        .iter()
+        .enumerate()
+        .map(|(i, w)| match i {
+            _ if keeps_case(w, shouting) => w.to_owned(),
+            0 => w.to_lowercase(),
+            _ => capitalize(w),
+        })
+        .collect()
+}
+
+fn split_camel(name: &str) -> String {
+    let chars = name.chars().collect::<Vec<_>>();
+    let mut res = String::with_capacity(name.len());
+    for (i, &c) in chars.iter().enumerate() {
+        if i > 0 && c.is_uppercase() {
+            let prev = chars[i - 1];
+            let next_lower = chars.get(i + 1).is_some_and(|n| n.is_lowercase());
+            // "fooBar" -> "foo Bar", and "HTMLParser" -> "HTML Parser".
+            if prev.is_lowercase() || prev.is_ascii_digit() || (prev.is_uppercase() && next_lower) {
+                res.push(' ');
+            }
+        }
+        res.push(c);
+    }
+    res
+}
+
+impl FromStr for Casing {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (style, lang) = s.split_once(':').unwrap_or((s, ""));
+        let lang = match lang.to_lowercase().as_str() {
+            "" | "en" => Lang::En,
+            "pt" => Lang::Pt,
+            "es" => Lang::Es,
+            "fr" => Lang::Fr,
+            "it" => Lang::It,
+            "de" => Lang::De,
+            x => {
+                return Err(format!(
+                    "unknown language: {x:?} (use en, pt, es, fr, it, or de)"
+                ));
+            }
+        };
+        let casing = match style.to_lowercase().as_str() {
+            "title" | "t" => Casing::Title(lang),
+            "sentence" | "s" => Casing::Sentence,
+            "upper" | "u" => Casing::Upper,
+            "lower" | "l" => Casing::Lower,
+            "snake" => Casing::Snake,
+            "kebab" => Casing::Kebab,
+            "camel" => Casing::Camel,
+            "split" => Casing::Split,
+            x => {
+                return Err(format!(
+                    "unknown casing: {x:?} (use title[:LANG], sentence, upper, lower, snake, kebab, camel, or split)"
+                ));
+            }
+        };
+        match (casing, lang) {
+            (Casing::Title(_), _) | (_, Lang::En) => Ok(casing),
+            _ => Err(format!("only title casing supports a language: {s:?}")),
+        }
+    }
+}
+
+impl Display for Casing {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Casing::Title(lang) => write!(f, "title:{}", format!("{lang:?}").to_lowercase()),
+            Casing::Sentence => write!(f, "sentence"),
+            Casing::Upper => write!(f, "upper"),
+            Casing::Lower => write!(f, "lower"),
+            Casing::Snake => write!(f, "snake"),
+            Casing::Kebab => write!(f, "kebab"),
+            Casing::Camel => write!(f, "camel"),
+            Casing::Split => write!(f, "split"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn casings() {
+        #[track_caller]
+        fn case(casing: &str, name: &str, out: &str) {
+            let casing = casing.parse::<Casing>().unwrap();
+            assert_eq!(casing.apply(name), out);
+        }
+
+        case("title", "the lord of the rings", "The Lord of the Rings");
+        case(
+            "title",
+            "THE LORD OF THE RINGS - PART 1",
+            "The Lord of the Rings - Part 1",
+        );
+        case("title", "the NASA files", "The NASA Files");
+        case("title", "lost s03e05 the pilot", "Lost s03e05 the Pilot");
+        case("title", "LOST S03E05 THE PILOT", "Lost S03E05 the Pilot");
+        case("title", "a tale of BBC and 2pac", "A Tale of BBC and 2pac");
+        case("title", "of mice and men", "Of Mice and Men");
+        case("title", "what we're made of", "What We're Made Of");
+        case("title", "foo_bar.baz ", "Foo_Bar.Baz ");
+        case("title:pt", "o senhor dos anéis", "O Senhor dos Anéis");
+        case("title:de", "der herr der ringe", "Der Herr der Ringe");
+
+        case("sentence", "THE LORD of The Rings", "The lord of the rings");
+        case("sentence", " - 2 towers", " - 2 towers");
+        case("sentence", "(the) two towers", "(The) two towers");
+        case("upper", "foo Bar", "FOO BAR");
+        case("lower", "Foo BAR", "foo bar");
+
+        case("snake", "Foo Bar - baz", "foo_bar_baz");
+        case("snake", "fooBar baz2", "foo_bar_baz2");
+        case("kebab", "Foo_Bar.baz", "foo-bar-baz");
+        case("kebab", "HTMLParser v2", "html-parser-v2");
+        case("camel", "foo bar_baz", "fooBarBaz");
+        case("camel", "FOO-BAR", "fooBar");
+        case("camel", "the NASA files", "theNASAFiles");
+        case("camel", "show S03E05", "showS03E05");
+
+        case("split", "fooBarBaz", "foo Bar Baz");
+        case("split", "HTMLParser", "HTML Parser");
+        case("split", "Season2Episode10", "Season2 Episode10");
+        case("split", "foo bar", "foo bar");
+    }
+
+    #[test]
+    fn invalid_casings() {
+        assert!("nope".parse::<Casing>().is_err());
+        assert!("title:xx".parse::<Casing>().is_err());
+        assert!("upper:pt".parse::<Casing>().is_err());
+    }
+}
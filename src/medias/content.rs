@@ -0,0 +1,72 @@
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::Path;
+
+/// The default sample size, for a quick check of whether files might be identical.
+pub const SAMPLE_SIZE: usize = 4 * 1024;
+
+/// Read a sample of a file, from its start, middle, and end, or the whole file if it is smaller
+/// than the sample size.
+pub fn file_sample(path: &Path, file_len: u64, size: usize) -> io::Result<Vec<u8>> {
+    let mut file = File::open(path)?;
+
+    if file_len <= size as u64 {
+        // read the whole file if it's smaller than the sample size.
+        let mut buf = Vec::with_capacity(file_len as usize);
+        file.read_to_end(&mut buf)?;
+        return Ok(buf);
+    }
+
+    // allocate buffer for all chunks.
+    let mut buf = vec![0; size];
+    let chunk_size = size / 3; // may not be divisible by 3, but that's okay.
+
+    // read from the start.
+    file.read_exact(&mut buf[..chunk_size])?;
+
+    // read from the middle.
+    let mid_pos = file_len / 2 - chunk_size as u64 / 2;
+    file.seek(SeekFrom::Start(mid_pos))?;
+    file.read_exact(&mut buf[chunk_size..chunk_size * 2])?;
+
+    // read from the end; this last chunk must compensate for the remainder of division.
+    let end_pos = file_len - (size - chunk_size * 2) as u64;
+    file.seek(SeekFrom::Start(end_pos))?;
+    file.read_exact(&mut buf[chunk_size * 2..])?;
+
+    Ok(buf)
+}
+
+/// Check whether two files have the same content, by size and sample, and then byte by byte.
+///
+/// Directories and unreadable files are never the same.
+pub fn same_content(p: &Path, q: &Path) -> bool {
+    let (Ok(mp), Ok(mq)) = (p.metadata(), q.metadata()) else {
+        return false;
+    };
+    if mp.is_dir() || mq.is_dir() || mp.len() != mq.len() {
+        return false;
+    }
+    let sample = |path| file_sample(path, mp.len(), SAMPLE_SIZE).ok();
+    if sample(p).is_none_or(|s| Some(s) != sample(q)) {
+        return false;
+    }
+    if mp.len() <= SAMPLE_SIZE as u64 {
+        return true; // the samples were the whole files.
+    }
+
+    let (Ok(mut fp), Ok(mut fq)) = (File::open(p), File::open(q)) else {
+        return false;
+    };
+    let (mut bp, mut bq) = (vec![0; 1 << 16], vec![0; 1 << 16]);
+    let mut left = mp.len();
+    while left > 0 {
+        let n = left.min(bp.len() as u64) as usize;
+        let (bp, bq) = (&mut bp[..n], &mut bq[..n]);
+        if fp.read_exact(bp).is_err() || fq.read_exact(bq).is_err() || bp != bq {
+            return false;
+        }
+        left -= n as u64;
+    }
+    true
+}
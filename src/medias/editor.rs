@@ -0,0 +1,154 @@
+use crate::entries::Entry;
+use crate::utils;
+use anyhow::{Context, Result, anyhow};
+use std::fmt::Write;
+use std::fs::File;
+use std::hash::{BuildHasher, Hasher, RandomState};
+use std::io::{self, Write as _};
+use std::path::PathBuf;
+use std::{env, fs};
+
+/// Let the user edit new names in their editor, vidir-style, returning the edited ones.
+///
+/// Each name is written on a numbered line, grouped by parent directory. Removing a line leaves its
+/// entry unchanged, which is returned as None.
+pub fn edit_names(names: &[(&Entry, String)]) -> Result<Vec<Option<String>>> {
+    // step: write the names to a temporary file.
+    let mut text = String::from(
+        "# Edit the new names, keeping the numbers; remove a line to leave its entry unchanged.\n",
+    );
+    let mut last = None;
+    for (i, (entry, name)) in names.iter().enumerate() {
+        let parent = entry.parent();
+        if parent != last {
+            let dir = parent.as_ref().map(|p| p.to_str()).unwrap_or_default();
+            write!(text, "\n# {dir}/\n")?;
+            last = parent;
+        }
+        let slash = if entry.is_dir() { "/" } else { "" };
+        writeln!(text, "{}\t{name}{slash}", i + 1)?;
+    }
+    let temp = TempFile::create(&text)?;
+
+    // step: open the editor, and wait for it to finish.
+    utils::launch_editor(&temp.0).context("nothing was changed")?;
+
+    // step: read the edited names back.
+    let text = fs::read_to_string(&temp.0).with_context(|| format!("reading {:?}", temp.0))?;
+    parse(&text, names.len())
+}
+
+/// A temporary file with a random name, which is removed when dropped.
+struct TempFile(PathBuf);
+
+impl TempFile {
+    /// Create a new temporary file with the given contents; it never opens an existing file or
+    /// link, so its path can't be hijacked by other users.
+    fn create(contents: &str) -> Result<TempFile> {
+        let (temp, mut file) = loop {
+            let n = RandomState::new().build_hasher().finish();
+            let path = env::temp_dir().join(format!("refine-{n:016x}.txt"));
+            match File::create_new(&path) {
+                Ok(file) => break (TempFile(path), file),
+                Err(err) if err.kind() == io::ErrorKind::AlreadyExists => continue,
+                Err(err) => return Err(err).with_context(|| format!("creating {path:?}")),
+            }
+        };
+        file.write_all(contents.as_bytes())
+            .with_context(|| format!("writing {:?}", temp.0))?;
+        Ok(temp)
+    }
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.0);
+    }
+}
+
+/// Parse the edited text, validating the numbers and names.
+fn parse(text: &str, len: usize) -> Result<Vec<Option<String>>> {
+    let mut names = vec![None; len];
+    for (line, n) in text.lines().zip(1..) {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let err = |msg: &str| anyhow!("{msg} in line {n}: {line:?}");
+        let (idx, name) = line
+            .split_once(char::is_whitespace)
+            .ok_or_else(|| err("missing name"))?;
+        let idx = idx
+            .parse::<usize>()
+            .ok()
+            .filter(|i| (1..=len).contains(i))
+            .ok_or_else(|| err("invalid number"))?;
+        let name = name.trim();
+        let name = name.strip_suffix('/').unwrap_or(name).trim();
+        if name.is_empty() || name.contains(['/', '\\']) {
+            return Err(err("invalid name"));
+        }
+        if names[idx - 1].replace(name.to_owned()).is_some() {
+            return Err(err("duplicated number"));
+        }
+    }
+    Ok(names)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn edited() {
+        #[track_caller]
+        fn case(text: &str, len: usize, out: &[Option<&str>]) {
+            let out = out.iter().map(|o| o.map(str::to_owned)).collect::<Vec<_>>();
+            assert_eq!(parse(text, len).unwrap(), out);
+        }
+
+        case(
+            "1\tfoo.txt\n2\tbar.txt",
+            2,
+            &[Some("foo.txt"), Some("bar.txt")],
+        );
+        case("# ./a/\n\n2\tbar.txt\n", 2, &[None, Some("bar.txt")]);
+        case(
+            "2 bar baz.txt\n1 dir/",
+            2,
+            &[Some("dir"), Some("bar baz.txt")],
+        );
+        case("1\t  foo  ", 1, &[Some("foo")]);
+        case("", 1, &[None]);
+    }
+
+    #[test]
+    fn invalid_edits() {
+        #[track_caller]
+        fn case(text: &str) {
+            assert!(parse(text, 2).is_err(), "{text}");
+        }
+
+        case("foo.txt");
+        case("1");
+        case("0\tfoo");
+        case("3\tfoo");
+        case("x\tfoo");
+        case("1\ta/b");
+        case("1\t/");
+        case("1\tfoo\n1\tbar");
+    }
+
+    #[test]
+    fn temp_files() {
+        let (a, b) = (
+            TempFile::create("foo").unwrap(),
+            TempFile::create("").unwrap(),
+        );
+        assert_ne!(a.0, b.0);
+        assert_eq!(fs::read_to_string(&a.0).unwrap(), "foo");
+        let path = a.0.clone();
+        drop(a);
+        assert!(!path.exists());
+    }
+}
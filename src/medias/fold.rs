@@ -0,0 +1,86 @@
+use crate::entries::Entry;
+use std::borrow::Cow;
+use std::collections::HashSet;
+use std::path::Path;
+
+/// Get the key used to compare names, which is lowercased on case-insensitive file systems.
+pub fn fold_key(name: &str, fold: bool) -> Cow<'_, str> {
+    match fold {
+        false => Cow::Borrowed(name),
+        true => Cow::Owned(name.to_lowercase()),
+    }
+}
+
+/// Check whether the file system that holds the given path is case-insensitive.
+///
+/// It looks for the nearest existing ancestor with a cased name, and checks whether the same path
+/// with the case swapped is the same directory; nothing is ever written to the file system.
+pub fn is_case_insensitive(path: &Path) -> bool {
+    let Some(Ok(abs)) = path
+        .ancestors()
+        .find(|p| p.exists())
+        .map(Path::canonicalize)
+    else {
+        return false;
+    };
+    abs.ancestors()
+        .find_map(|p| {
+            let name = p.file_name()?.to_str()?;
+            let swapped = name
+                .chars()
+                .flat_map(|c| match c.is_uppercase() {
+                    true => c.to_lowercase().collect::<Vec<_>>(),
+                    false => c.to_uppercase().collect(),
+                })
+                .collect::<String>();
+            (swapped != name).then(|| is_same_file(p, &p.with_file_name(swapped)))
+        })
+        .unwrap_or_default()
+}
+
+/// Check whether both paths point to the same file, by device and inode on unix, or by volume and
+/// file index on Windows; any error means they are not.
+pub(super) fn is_same_file(p: &Path, q: &Path) -> bool {
+    same_file::is_same_file(p, q).unwrap_or_default()
+}
+
+/// Check whether any parent directory of the given entries is on a case-insensitive file system.
+pub fn parents_case_insensitive<'a>(entries: impl Iterator<Item = &'a Entry>) -> bool {
+    let parents = entries.filter_map(Entry::parent).collect::<HashSet<_>>();
+    parents.iter().any(|p| is_case_insensitive(p))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::Scratch;
+    use std::fs;
+
+    #[test]
+    fn fold_keys() {
+        #[track_caller]
+        fn case(name: &str, fold: bool, out: &str) {
+            assert_eq!(fold_key(name, fold), out);
+        }
+
+        case("Foo Bar.MP4", false, "Foo Bar.MP4");
+        case("Foo Bar.MP4", true, "foo bar.mp4");
+        case("ÉCOLE", true, "école");
+        case("foo", true, "foo");
+    }
+
+    #[test]
+    fn case_insensitive() {
+        let dir = Scratch::new("fold");
+        fs::create_dir(dir.join("Case")).unwrap();
+        let insensitive = fs::create_dir(dir.join("cASE")).is_err(); // it already "exists" there.
+
+        assert_eq!(is_case_insensitive(&dir.join("Case")), insensitive);
+        assert_eq!(
+            is_case_insensitive(&dir.join("Case/missing/a.txt")),
+            insensitive
+        );
+        assert_eq!(is_case_insensitive(&dir.join("123/456")), insensitive);
+        assert!(!is_case_insensitive(Path::new("/nonexistent-refine/Case")));
+    }
+}
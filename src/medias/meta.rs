@@ -0,0 +1,183 @@
+use crate::entries::Entry;
+use chrono::{DateTime, Local, NaiveDate, NaiveDateTime, NaiveTime};
+use exif::{In, Reader, Tag, Value};
+use id3::TagLike;
+use mime_guess::MimeGuess;
+use regex::Regex;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+use std::sync::LazyLock;
+
+/// The tags of a photo, read from its EXIF metadata.
+#[derive(Debug, Default)]
+pub struct ExifTags {
+    pub taken: Option<NaiveDateTime>,
+    pub model: Option<String>,
+    pub gps: bool,
+}
+
+/// The tags of an audio file, read from its ID3 metadata.
+#[derive(Debug, Default)]
+pub struct AudioTags {
+    pub artist: Option<String>,
+    pub title: Option<String>,
+    pub album: Option<String>,
+    pub track: Option<u32>,
+}
+
+/// Classify the kind of media from a file extension, like "video", "image", or "document".
+pub fn media_kind(ext: &str) -> &'static str {
+    let ext = ext.to_ascii_lowercase();
+    let ext = ext.as_str();
+    // guess the mime type from the extension.
+    let mime = MimeGuess::from_ext(ext).first_raw().unwrap_or_default();
+    let top = mime.split('/').next().unwrap_or_default();
+
+    match top {
+        "video" | "audio" | "image" | "text" => top,
+        "application" => match ext {
+            // video extensions that are misclassified as application.
+            "mkv" | "webm" | "rmvb" | "m2ts" | "mts" | "f4v" | "vob" | "ogv" => "video",
+            // document.
+            "pdf" | "doc" | "docx" | "xls" | "xlsx" | "ppt" | "pptx" | "odt" | "ods" | "odp"
+            | "rtf" => "document",
+            // archive.
+            "zip" | "rar" | "7z" | "tar" | "gz" | "bz2" | "xz" | "lz" | "lzma" | "iso" | "cab"
+            | "arj" | "z" => "archive",
+            // subtitle.
+            "srt" | "ass" | "ssa" | "sub" | "vtt" | "idx" | "sup" => "subtitle",
+            // text (some application/* are actually text).
+            "csv" | "json" | "xml" | "yaml" | "yml" | "ini" | "conf" => "text",
+            _ => "application",
+        },
+        _ => "unknown",
+    }
+}
+
+/// Read the EXIF tags of an image file, if it has any.
+pub fn exif_tags(entry: &Entry) -> Option<ExifTags> {
+    if media_kind(entry.filename_parts().1) != "image" {
+        return None;
+    }
+    let file = File::open(entry).ok()?;
+    let exif = Reader::new()
+        .read_from_container(&mut BufReader::new(file))
+        .ok()?;
+    let ascii = |tag| match &exif.get_field(tag, In::PRIMARY)?.value {
+        Value::Ascii(values) => std::str::from_utf8(values.first()?)
+            .ok()
+            .map(|s| {
+                s.trim_matches(|c: char| c == '\0' || c.is_whitespace())
+                    .to_owned()
+            })
+            .filter(|s| !s.is_empty()),
+        _ => None,
+    };
+    Some(ExifTags {
+        taken: ascii(Tag::DateTimeOriginal)
+            .and_then(|s| NaiveDateTime::parse_from_str(&s, "%Y:%m:%d %H:%M:%S").ok()),
+        model: ascii(Tag::Model),
+        gps: exif.get_field(Tag::GPSLatitude, In::PRIMARY).is_some(),
+    })
+}
+
+/// Read the ID3 tags of an audio file, if it has any.
+pub fn audio_tags(path: &Path) -> Option<AudioTags> {
+    let tag = id3::Tag::read_from_path(path).ok()?;
+    Some(AudioTags {
+        artist: tag.artist().map(str::to_owned),
+        title: tag.title().map(str::to_owned),
+        album: tag.album().map(str::to_owned),
+        track: tag.track(),
+    })
+}
+
+/// Find a date embedded in a file name, like "20240601_153012", "2024-06-01", or the WhatsApp
+/// "2024-06-01 at 3.30.12 PM".
+pub fn name_date(name: &str) -> Option<NaiveDateTime> {
+    // regex: a date with optional separators, optionally followed by a time, either in the
+    // WhatsApp " at H.MM.SS [AM|PM]" format or in the compact "HHMMSS" with optional separators.
+    static RE: LazyLock<Regex> = LazyLock::new(|| {
+        Regex::new(
+            r"(?i)(?:^|\D)((?:19|20)\d{2})[-_.]?(\d{2})[-_.]?(\d{2})(?: at (\d{1,2})\.(\d{2})(?:\.(\d{2}))?(?: ?([AP]M))?|[-_ T.]?(\d{2})[-_.:]?(\d{2})(?:[-_.:]?(\d{2}))?|\D|$)",
+        )
+        .unwrap()
+    });
+
+    RE.captures_iter(name).find_map(|caps| {
+        let num = |i: usize| caps.get(i).map_or(Some(0), |m| m.as_str().parse().ok());
+        let date = NaiveDate::from_ymd_opt(num(1)? as i32, num(2)?, num(3)?)?;
+        let (h, m, s) = match caps.get(4) {
+            Some(_) => (num(4)?, num(5)?, num(6)?),
+            None => (num(8)?, num(9)?, num(10)?),
+        };
+        let h = match caps.get(7).map(|m| m.as_str().to_ascii_uppercase()) {
+            Some(x) if x == "PM" && h < 12 => h + 12,
+            Some(x) if x == "AM" && h == 12 => 0,
+            _ => h,
+        };
+        let time = NaiveTime::from_hms_opt(h, m, s).unwrap_or_default();
+        Some(date.and_time(time))
+    })
+}
+
+/// The best date of a media file: when the photo was taken, a date in its name, or its mtime.
+pub fn media_date(entry: &Entry) -> Option<NaiveDateTime> {
+    exif_tags(entry)
+        .and_then(|t| t.taken)
+        .or_else(|| name_date(entry.filename_parts().0))
+        .or_else(|| {
+            let mtime = entry.metadata().ok()?.modified().ok()?;
+            Some(DateTime::<Local>::from(mtime).naive_local())
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn name_dates() {
+        #[track_caller]
+        fn case(name: &str, out: Option<&str>) {
+            let out = out.map(|s| NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M:%S").unwrap());
+            assert_eq!(name_date(name), out);
+        }
+
+        case("IMG_20240601_153012", Some("2024-06-01 15:30:12"));
+        case("PXL_20240601_153012345", Some("2024-06-01 15:30:12"));
+        case("20240601_1530", Some("2024-06-01 15:30:00"));
+        case("trip 2024-06-01", Some("2024-06-01 00:00:00"));
+        case("2024.06.01 party", Some("2024-06-01 00:00:00"));
+        case("IMG-20240601-WA0001", Some("2024-06-01 00:00:00"));
+        case("signal-2024-06-01-153012", Some("2024-06-01 15:30:12"));
+        case(
+            "signal-2024-06-01-15-30-12-123",
+            Some("2024-06-01 15:30:12"),
+        );
+        case("Screenshot_20240601-153012", Some("2024-06-01 15:30:12"));
+        case(
+            "WhatsApp Image 2024-06-01 at 15.30.12",
+            Some("2024-06-01 15:30:12"),
+        );
+        case(
+            "WhatsApp Image 2020-01-02 at 3.04.05 PM",
+            Some("2020-01-02 15:04:05"),
+        );
+        case(
+            "WhatsApp Video 2020-01-02 at 12.04.05 am (1)",
+            Some("2020-01-02 00:04:05"),
+        );
+        case(
+            "WhatsApp Audio 2020-01-02 at 9.04",
+            Some("2020-01-02 09:04:00"),
+        );
+        case("20241301 bad month 2023-02-03", Some("2023-02-03 00:00:00"));
+
+        case("IMG_1234", None);
+        case("DSC_20240", None);
+        case("120240601", None);
+        case("foo", None);
+    }
+}
@@ -1,9 +1,27 @@
+mod casing;
+mod content;
+mod editor;
+mod fold;
+mod meta;
 mod naming;
 mod ops;
+mod plan;
+mod review;
+mod sidecar;
+mod template;
 
 use crate::entries::Entry;
+pub use casing::*;
+pub use content::*;
+pub use editor::*;
+pub use fold::*;
+pub use meta::*;
 pub use naming::*;
 pub use ops::*;
+pub use plan::*;
+pub use review::*;
+pub use sidecar::*;
+pub use template::*;
 
 pub trait SourceEntry {
     /// The original entry of the file.
@@ -1,11 +1,17 @@
-use super::{NewNameMut, SourceEntry};
+use super::{Casing, NewNameMut, SourceEntry};
+use crate::entries::Entry;
 use crate::utils;
-use anyhow::{Context, Result};
+use anyhow::{Context, Result, anyhow};
 use clap::Args;
 use clap::builder::NonEmptyStringValueParser;
 use regex::Regex;
 use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fmt::{self, Display};
+use std::fs;
+use std::path::PathBuf;
 use std::sync::LazyLock;
+use yansi::Color;
 
 /// A set of rules that allows the user to customize filenames.
 #[derive(Debug, Args)]
@@ -25,75 +31,188 @@ pub struct Naming {
     /// recipe: Throw some prefix to the end; use {S} if needed.
     #[arg(short = 'w', long, value_name = "STR|REGEX=STR", allow_hyphen_values = true, value_parser = utils::parse_key_value::<String, String>)]
     throw: Vec<(String, String)>,
+    /// recipe: Change the case after the other rules: title[:LANG], sentence, upper, lower, snake, kebab, camel, or split.
+    #[arg(long, value_name = "STR")]
+    casing: Vec<Casing>,
+    /// Load rules from a file, one "<rule> <value>" per line (e.g. "strip-before foo"), applied in order before the others.
+    #[arg(long, value_name = "FILE")]
+    rules: Vec<PathBuf>,
+    /// Trace the rules that changed each name, and report the ones that never matched.
+    #[arg(long)]
+    trace: bool,
 }
 
 impl Naming {
     /// Compile this set of rules.
     pub fn compile(&self) -> Result<NamingRules> {
-        NamingRules::compile(
-            [&self.strip_before, &self.strip_after, &self.strip_exact],
-            &self.replace,
-            &self.throw,
-        )
+        let mut rules = self
+            .rules
+            .iter()
+            .map(|path| {
+                let text = fs::read_to_string(path).with_context(|| format!("reading {path:?}"))?;
+                let origin = path.file_name().unwrap_or_default().to_string_lossy();
+                NamingRules::parse(&text, &origin)
+                    .with_context(|| format!("in rules file {path:?}"))
+            })
+            .collect::<Result<Vec<_>>>()?
+            .into_iter()
+            .flat_map(|r| r.rules)
+            .collect::<Vec<_>>();
+        rules.extend(
+            NamingRules::compile(
+                [&self.strip_before, &self.strip_after, &self.strip_exact],
+                &self.replace,
+                &self.throw,
+            )?
+            .rules,
+        );
+        rules.extend(self.casing.iter().map(|&c| Rule {
+            op: Op::Casing(c),
+            label: format!("--casing {c}"),
+        }));
+        let trace = self.trace.then(|| Trace {
+            hits: HashMap::new(),
+            used: vec![false; rules.len()],
+        });
+        Ok(NamingRules { rules, trace })
+    }
+}
+
+impl NamingRules {
+    /// Whether any rule changes the case, so the names must keep theirs until then.
+    pub fn has_casing(&self) -> bool {
+        self.rules.iter().any(|r| matches!(r.op, Op::Casing(_)))
     }
 }
 
+/// The kinds of naming rules, which define how their regexes are generated.
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Kind {
+    StripBefore,
+    StripAfter,
+    StripExact,
+    Replace,
+    Throw,
+}
+
+#[derive(Debug)]
+pub struct NamingRules {
+    rules: Vec<Rule>,
+    trace: Option<Trace>,
+}
+
+/// A compiled rule, along with a label that identifies it to the user.
+#[derive(Debug)]
+struct Rule {
+    op: Op,
+    label: String,
+}
+
+/// What a rule does to a name.
 #[derive(Debug)]
-pub struct NamingRules(Vec<(Regex, String)>);
+enum Op {
+    /// Replace the matches of a regex.
+    Regex(Regex, String),
+    /// Change the case of the name.
+    Casing(Casing),
+}
+
+/// The rules that changed each name in order, and which rules matched any name at all.
+#[derive(Debug)]
+struct Trace {
+    hits: HashMap<Entry, Vec<Hit>>,
+    used: Vec<bool>,
+}
+
+/// A single rule that matched a name, along with the name before and after it.
+#[derive(Debug)]
+struct Hit {
+    rule: usize,
+    before: String,
+    after: String,
+}
 
 impl NamingRules {
+    /// Compile rules grouped by kind, which are applied in this fixed order.
     fn compile(
         strip_rules: [&[impl AsRef<str>]; 3],
         replace_rules: &[(impl AsRef<str>, impl AsRef<str>)],
         throw_rules: &[(impl AsRef<str>, impl AsRef<str>)],
     ) -> Result<NamingRules> {
-        const O: &str = r"[(\[{]"; // enclosing opening.
-        const C: &str = r"[)\]}]"; // enclosing closing.
-        const SEP: &str = r"[-\s.,]";
-        let before = |rule| format!("^.*{rule}{C}*{SEP}*");
-        let after = |rule| format!("{SEP}*{O}*{rule}.*$");
-        let exact = |rule| {
-            static RE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"\w$").unwrap());
-            let b = if RE.is_match(rule) { r"\b" } else { r"\B" };
-            format!(
-                r"^{O}*{rule}{C}*{SEP}+|{SEP}+{O}*{rule}{C}*$|{SEP}+{O}*{rule}{C}*{b}|{O}*{rule}{C}*"
-            )
-        };
-        let replace_key = |rule: &str| rule.to_owned();
-        let throw_key = |rule| format!(r"^{rule}{SEP}+(.+)$");
-        let throw_value = |val| format!(r"$1 - {val}");
-
         let rules = strip_rules
             .into_iter()
-            .map(|g| {
-                g.iter()
-                    .map(|r| (r.as_ref(), String::new()))
-                    .collect::<Vec<_>>()
-            })
-            .chain([replace_rules
-                .iter()
-                .map(|(k, v)| (k.as_ref(), v.as_ref().to_owned()))
-                .collect()])
-            .chain([throw_rules
-                .iter()
-                .map(|(k, v)| (k.as_ref(), throw_value(v.as_ref())))
-                .collect()])
-            .zip([before, after, exact, replace_key, throw_key])
-            .flat_map(|(g, f)| g.into_iter().map(move |(k, v)| (k, v, f)))
-            .map(|(rule, to, f)| {
-                Regex::new(&format!("(?i){}", f(rule).replace("{S}", SEP))) // support {S} for separators.
-                    .with_context(|| format!("compiling regex: {rule:?}"))
-                    .map(|re| (re, to))
+            .zip([Kind::StripBefore, Kind::StripAfter, Kind::StripExact])
+            .flat_map(|(g, kind)| g.iter().map(move |r| (kind, r.as_ref(), "")))
+            .chain(
+                replace_rules
+                    .iter()
+                    .map(|(k, v)| (Kind::Replace, k.as_ref(), v.as_ref())),
+            )
+            .chain(
+                throw_rules
+                    .iter()
+                    .map(|(k, v)| (Kind::Throw, k.as_ref(), v.as_ref())),
+            )
+            .map(|(kind, rule, to)| kind.compile(rule, to))
+            .collect::<Result<_>>()?;
+        Ok(NamingRules { rules, trace: None })
+    }
+
+    /// Parse and compile rules from the content of a rules file, which are applied in order.
+    ///
+    /// Each line has a rule kind and its value separated by spaces, like "strip-before foo" or
+    /// "replace foo=bar"; the kinds can also be given by their short options, like "b foo".
+    /// Casing recipes are also supported, like "casing title:en".
+    /// Empty lines and lines starting with `#` are ignored.
+    fn parse(text: &str, origin: &str) -> Result<NamingRules> {
+        let parse_line = |line: &str| {
+            let (kind, value) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+            if kind == "casing" {
+                let casing = value.trim().parse::<Casing>().map_err(|err| anyhow!(err))?;
+                let op = Op::Casing(casing);
+                return Ok(Rule {
+                    op,
+                    label: "".to_owned(),
+                });
+            }
+            let kind = match kind {
+                "b" | "strip-before" => Kind::StripBefore,
+                "a" | "strip-after" => Kind::StripAfter,
+                "e" | "strip-exact" => Kind::StripExact,
+                "r" | "replace" => Kind::Replace,
+                "w" | "throw" => Kind::Throw,
+                _ => return Err(anyhow!("unknown rule: {kind:?}")),
+            };
+            let (rule, to) = match (kind, value.trim()) {
+                (_, "") => return Err(anyhow!("missing value")),
+                (Kind::Replace | Kind::Throw, value) => utils::parse_key_value(value)?,
+                (_, value) => (value.to_owned(), String::new()),
+            };
+            kind.compile(&rule, &to)
+        };
+        let rules = text
+            .lines()
+            .zip(1..)
+            .map(|(line, n)| (line.trim(), n))
+            .filter(|(line, _)| !line.is_empty() && !line.starts_with('#'))
+            .map(|(line, n)| {
+                parse_line(line)
+                    .map(|rule| Rule {
+                        label: format!("{origin}:{n}: {line}"),
+                        ..rule
+                    })
+                    .with_context(|| format!("line {n}: {line:?}"))
             })
             .collect::<Result<_>>()?;
-        Ok(NamingRules(rules))
+        Ok(NamingRules { rules, trace: None })
     }
 
     /// Apply these rules to a list of media, consuming the entries that got their names cleared.
     ///
     /// The [NewNameMut] is used as the starting point, and is mutated in place.
     /// It returns the number of entries that were cleared by the rules.
-    pub fn apply(&self, medias: &mut Vec<impl SourceEntry + NewNameMut>) -> usize {
+    /// If tracing is enabled, the rules that changed each name are recorded.
+    pub fn apply(&mut self, medias: &mut Vec<impl SourceEntry + NewNameMut>) -> usize {
         // this is just so that warnings are printed in a consistent order.
         medias.sort_unstable_by(|m, n| m.src_entry().cmp(n.src_entry()));
 
@@ -101,14 +220,30 @@ impl NamingRules {
         let total = medias.len();
         medias.retain_mut(|m| {
             let mut name = std::mem::take(m.new_name_mut());
-            self.0.iter().for_each(|(re, to)| {
-                if let Cow::Owned(x) = re.replace_all(&name, to) {
-                    name = x;
+            let mut hits = vec![];
+            self.rules.iter().enumerate().for_each(|(i, rule)| {
+                if let Cow::Owned(x) = rule.apply(&name) {
+                    if self.trace.is_some() {
+                        let before = std::mem::replace(&mut name, x);
+                        let after = name.clone();
+                        hits.push(Hit {
+                            rule: i,
+                            before,
+                            after,
+                        });
+                    } else {
+                        name = x;
+                    }
                 }
             });
+            if let Some(trace) = &mut self.trace {
+                hits.iter().for_each(|h| trace.used[h.rule] = true);
+                trace.hits.insert(m.src_entry().clone(), hits);
+            }
 
             if name.is_empty() {
                 eprintln!("blocked: rules cleared name: {}", m.src_entry());
+                eprint!("{}", self.display_trace(m.src_entry()));
                 return false;
             }
             *m.new_name_mut() = name;
@@ -116,12 +251,126 @@ impl NamingRules {
         });
         total - medias.len()
     }
+
+    /// Return an object that displays the rules that changed the name of an entry, if tracing.
+    pub fn display_trace<'a>(&'a self, entry: &'a Entry) -> impl Display + 'a {
+        DisplayTrace { rules: self, entry }
+    }
+
+    /// Return an object that displays the rules that never matched any name, if tracing.
+    pub fn display_unused(&self) -> impl Display + '_ {
+        DisplayUnused(self)
+    }
+}
+
+#[derive(Debug)]
+pub struct DisplayTrace<'a> {
+    rules: &'a NamingRules,
+    entry: &'a Entry,
+}
+
+#[derive(Debug)]
+pub struct DisplayUnused<'a>(&'a NamingRules);
+
+impl Display for DisplayTrace<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use yansi::Paint;
+        let Some(trace) = &self.rules.trace else {
+            return Ok(());
+        };
+        trace
+            .hits
+            .get(self.entry)
+            .into_iter()
+            .flatten()
+            .try_for_each(|h| {
+                let label = &self.rules.rules[h.rule].label;
+                writeln!(
+                    f,
+                    "    {} {:?} -> {:?}",
+                    label.paint(Color::BrightBlack),
+                    h.before,
+                    h.after
+                )
+            })
+    }
+}
+
+impl Display for DisplayUnused<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use yansi::Paint;
+        let Some(trace) = &self.0.trace else {
+            return Ok(());
+        };
+        let unused = (self.0.rules.iter().zip(&trace.used))
+            .filter(|(_, used)| !**used)
+            .map(|(rule, _)| &rule.label)
+            .collect::<Vec<_>>();
+        writeln!(f, "  unused rules: {}", unused.len())?;
+        unused
+            .iter()
+            .try_for_each(|label| writeln!(f, "    {}", label.paint(Color::BrightBlack)))
+    }
+}
+
+impl Rule {
+    /// Apply this rule to a name, returning an owned value only if it changed.
+    fn apply<'a>(&self, name: &'a str) -> Cow<'a, str> {
+        match &self.op {
+            Op::Regex(re, to) => re.replace_all(name, to),
+            Op::Casing(casing) => casing.apply(name),
+        }
+    }
+}
+
+impl Kind {
+    /// The name of the option that generates this kind of rule.
+    fn name(self) -> &'static str {
+        match self {
+            Kind::StripBefore => "strip-before",
+            Kind::StripAfter => "strip-after",
+            Kind::StripExact => "strip-exact",
+            Kind::Replace => "replace",
+            Kind::Throw => "throw",
+        }
+    }
+
+    /// Compile a rule into a regex and its replacement.
+    fn compile(self, rule: &str, to: &str) -> Result<Rule> {
+        let label = match self {
+            Kind::Replace | Kind::Throw => format!("--{} {rule}={to}", self.name()),
+            _ => format!("--{} {rule}", self.name()),
+        };
+        const O: &str = r"[(\[{]"; // enclosing opening.
+        const C: &str = r"[)\]}]"; // enclosing closing.
+        const SEP: &str = r"[-\s.,]";
+        let (re, to) = match self {
+            Kind::StripBefore => (format!("^.*{rule}{C}*{SEP}*"), String::new()),
+            Kind::StripAfter => (format!("{SEP}*{O}*{rule}.*$"), String::new()),
+            Kind::StripExact => {
+                static RE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"\w$").unwrap());
+                let b = if RE.is_match(rule) { r"\b" } else { r"\B" };
+                let re = format!(
+                    r"^{O}*{rule}{C}*{SEP}+|{SEP}+{O}*{rule}{C}*$|{SEP}+{O}*{rule}{C}*{b}|{O}*{rule}{C}*"
+                );
+                (re, String::new())
+            }
+            Kind::Replace => (rule.to_owned(), to.to_owned()),
+            Kind::Throw => (format!(r"^{rule}{SEP}+(.+)$"), format!(r"$1 - {to}")),
+        };
+        Regex::new(&format!("(?i){}", re.replace("{S}", SEP))) // support {S} for separators.
+            .with_context(|| format!("compiling regex: {rule:?}"))
+            .map(|re| Rule {
+                op: Op::Regex(re, to),
+                label,
+            })
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::entries::{Entry, ROOT};
+    use crate::entries::ROOT;
 
     const NO_STRIP: [&[&str]; 3] = [&[], &[], &[]];
     const NO_REPLACE: &[(&str, &str)] = &[];
@@ -148,7 +397,7 @@ mod tests {
             let mut strip_rules = [[].as_ref(); 3];
             strip_rules[idx] = rule;
             let mut medias = vec![Media(stem.to_owned())];
-            let rules = NamingRules::compile(strip_rules, NO_REPLACE, NO_THROW).unwrap();
+            let mut rules = NamingRules::compile(strip_rules, NO_REPLACE, NO_THROW).unwrap();
             let warnings = rules.apply(&mut medias);
             assert_eq!(warnings, 0);
             assert_eq!(medias[0].0, new_name);
@@ -207,7 +456,7 @@ mod tests {
         #[track_caller]
         fn case(replace_rules: &[(&str, &str)], stem: &str, new_name: &str) {
             let mut medias = vec![Media(stem.to_owned())];
-            let rules = NamingRules::compile(NO_STRIP, replace_rules, NO_THROW).unwrap();
+            let mut rules = NamingRules::compile(NO_STRIP, replace_rules, NO_THROW).unwrap();
             let warnings = rules.apply(&mut medias);
             assert_eq!(warnings, 0);
             assert_eq!(medias[0].0, new_name);
@@ -223,7 +472,7 @@ mod tests {
         #[track_caller]
         fn case(throw_rules: &[(&str, &str)], stem: &str, new_name: &str) {
             let mut medias = vec![Media(stem.to_owned())];
-            let rules = NamingRules::compile(NO_STRIP, NO_REPLACE, throw_rules).unwrap();
+            let mut rules = NamingRules::compile(NO_STRIP, NO_REPLACE, throw_rules).unwrap();
             let warnings = rules.apply(&mut medias);
             assert_eq!(warnings, 0);
             assert_eq!(medias[0].0, new_name);
@@ -281,10 +530,145 @@ mod tests {
             Media("refine".to_owned()),
             Media("foobar".to_owned()),
         ];
-        let rules =
+        let mut rules =
             NamingRules::compile([&["e"], &["b"], &["c.*i"]], &[("on", "")], NO_THROW).unwrap();
         let warnings = rules.apply(&mut medias);
         assert_eq!(warnings, 4);
         assert_eq!(medias, vec![Media("foo".to_owned())]);
     }
+
+    #[test]
+    fn rules_file() {
+        #[track_caller]
+        fn case(text: &str, stem: &str, new_name: &str) {
+            let mut medias = vec![Media(stem.to_owned())];
+            let mut rules = NamingRules::parse(text, "test").unwrap();
+            let warnings = rules.apply(&mut medias);
+            assert_eq!(warnings, 0);
+            assert_eq!(medias[0].0, new_name);
+        }
+
+        case("", "foo bar", "foo bar");
+        case("# comment\n\n  # indented comment", "foo bar", "foo bar");
+        case("strip-before foo", "foo bar", "bar");
+        case("b foo\na baz", "xfoo bar baz", "bar");
+        case("  strip-exact   bar  ", "foo bar baz", "foo baz");
+        case("replace -+=-", "foo---bar", "foo-bar");
+        case(
+            "w God{S}of{S}War=God of War",
+            "God of War media",
+            "media - God of War",
+        );
+
+        // the file order is kept, unlike the fixed order of the command line.
+        case("replace bar=foo\nstrip-exact foo", "bar baz", "baz");
+        case("strip-exact foo\nreplace bar=foo", "bar baz", "foo baz");
+        case(
+            "casing title\nreplace ^The=A",
+            "the lord of the rings",
+            "A Lord of the Rings",
+        );
+        case(
+            "replace _+=.\ncasing title:pt",
+            "o_senhor_dos_anéis",
+            "O.Senhor.dos.Anéis",
+        );
+        case("casing kebab\nstrip-exact bar", "Foo Bar Baz", "foo-baz");
+    }
+
+    #[test]
+    fn rules_file_errors() {
+        #[track_caller]
+        fn case(text: &str, line: usize) {
+            let err = NamingRules::parse(text, "test").unwrap_err();
+            assert!(
+                err.to_string().starts_with(&format!("line {line}:")),
+                "{err}"
+            );
+        }
+
+        case("nope foo", 1);
+        case("# comment\nstrip-before", 2);
+        case("b foo\n\nreplace foo", 3);
+        case("b foo\nthrow (foo=bar", 2);
+        case("casing nope", 1);
+        case("b foo\ncasing upper:pt", 2);
+    }
+
+    #[test]
+    fn traces() {
+        struct Named(Entry, String);
+        impl SourceEntry for Named {
+            fn src_entry(&self) -> &Entry {
+                &self.0
+            }
+        }
+        impl NewNameMut for Named {
+            fn new_name_mut(&mut self) -> &mut String {
+                &mut self.1
+            }
+        }
+
+        /// Display without the colors.
+        fn plain(d: impl Display) -> String {
+            let re = Regex::new(r"\x1b\[[0-9;]*m").unwrap();
+            re.replace_all(&d.to_string(), "").into_owned()
+        }
+
+        #[track_caller]
+        fn case(args: &[&str], names: &[&str], out: &[&str], unused: &str) {
+            use clap::FromArgMatches;
+            let cmd = Naming::augment_args(clap::Command::new("naming"));
+            let matches = cmd.get_matches_from(["naming"].iter().chain(args));
+            let mut rules = Naming::from_arg_matches(&matches)
+                .unwrap()
+                .compile()
+                .unwrap();
+            let entry = |n: &str| Entry::try_new(n, false).unwrap();
+            let mut medias = names
+                .iter()
+                .map(|&n| Named(entry(n), n.to_owned()))
+                .collect::<Vec<_>>();
+
+            rules.apply(&mut medias);
+            let traces = names
+                .iter()
+                .map(|&n| plain(rules.display_trace(&entry(n))))
+                .collect::<Vec<_>>();
+            assert_eq!(traces, out);
+            assert_eq!(plain(rules.display_unused()), unused);
+        }
+
+        let args = [
+            "-b", "foo", "-e", "nope", "-r", "bar=baz", "--casing", "upper",
+        ];
+        case(&args, &["foo x bar", "qux"], &["", ""], ""); // not tracing.
+        let args = [&args[..], &["--trace"]].concat();
+        case(
+            &args,
+            &["foo x bar", "qux"],
+            &[
+                concat!(
+                    "    --strip-before foo \"foo x bar\" -> \"x bar\"\n",
+                    "    --replace bar=baz \"x bar\" -> \"x baz\"\n",
+                    "    --casing upper \"x baz\" -> \"X BAZ\"\n",
+                ),
+                "    --casing upper \"qux\" -> \"QUX\"\n",
+            ],
+            "  unused rules: 1\n    --strip-exact nope\n",
+        );
+        case(
+            &args,
+            &["nope", "QUX"],
+            &["    --strip-exact nope \"nope\" -> \"\"\n", ""], // cleared, and unchanged.
+            "  unused rules: 3\n    --strip-before foo\n    --replace bar=baz\n    --casing upper\n",
+        );
+        case(
+            &["-a", "x", "--trace"],
+            &[],
+            &[],
+            "  unused rules: 1\n    --strip-after x\n",
+        );
+        case(&["--trace"], &["foo"], &[""], "  unused rules: 0\n");
+    }
 }
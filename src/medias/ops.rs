@@ -1,6 +1,16 @@
-use super::{NewEntry, SourceEntry};
-use std::io::Write;
-use std::path::Path;
+use super::fold::is_same_file;
+use super::{NewEntry, SourceEntry, same_content};
+use crate::entries::Entry;
+use crate::utils;
+use clap::Args;
+use human_repr::{HumanCount, HumanDuration, HumanThroughput};
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
+use std::hash::Hash;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
 use std::{fs, io};
 
 /// Implements file operations that consume the original media data on success.
@@ -8,37 +18,345 @@ pub struct FileOps;
 
 impl FileOps {
     /// Rename files and directories, or move them within the same file system.
+    ///
+    /// The renames are ordered so each target is vacated before it is used, even in chains like
+    /// a -> b -> c, and cycles like swaps are broken through temporary names.
     pub fn rename_move(medias: &mut Vec<impl SourceEntry + NewEntry>) {
-        files_op(medias, silent, |p, q| fs::rename(p, q))
+        let temps = order_renames(medias);
+        files_op(medias, "rename", silent, |p, q| match temps.get(p) {
+            Some(temp) => fs::rename(temp, q),
+            None => fs::rename(p, q),
+        });
+
+        // the ones that failed must not be left with temporary names.
+        medias
+            .iter()
+            .filter_map(|m| Some((m.src_entry(), temps.get(m.src_entry().as_ref())?)))
+            .for_each(|(src, temp)| {
+                if let Err(err) = fs::rename(temp, src) {
+                    eprintln!("error: {err}: restoring {temp} -> {src}");
+                }
+            });
     }
     /// Copy files to a new location, even if the file systems are different.
-    pub fn copy(medias: &mut Vec<impl SourceEntry + NewEntry>) {
-        files_op(medias, verbose, |p, q| copy_path(p, q, false, 0))
+    pub fn copy(medias: &mut Vec<impl SourceEntry + NewEntry>, transfer: &Transfer) {
+        let progress = Progress::new(medias);
+        files_op(medias, "copy", verbose, |p, q| {
+            transfer.copy(p, q, &progress)
+        })
     }
     /// Move files to a new location by copying and removing the original, even if the file systems are different.
-    pub fn cross_move(medias: &mut Vec<impl SourceEntry + NewEntry>) {
-        files_op(medias, verbose, |p, q| copy_path(p, q, true, 0))
+    ///
+    /// The originals are only removed after their copies are complete, and go to the trash unless
+    /// it is set to remove them permanently.
+    pub fn cross_move(medias: &mut Vec<impl SourceEntry + NewEntry>, transfer: &Transfer) {
+        let progress = Progress::new(medias);
+        files_op(medias, "move", verbose, |p, q| {
+            transfer
+                .copy(p, q, &progress)
+                .and_then(|()| transfer.trash.remove(p))
+        })
+    }
+    /// Clone files to a new location with copy-on-write, so they share data until either changes.
+    ///
+    /// It requires a file system that supports it, like Btrfs, XFS, or APFS, otherwise the files
+    /// are copied instead, which is noticed only once.
+    pub fn reflink(medias: &mut Vec<impl SourceEntry + NewEntry>, transfer: &Transfer) {
+        let progress = Progress::new(medias);
+        let noticed = Cell::new(false);
+        files_op(medias, "reflink", verbose, |p, q| {
+            link_tree(p, q, |p, q| reflink_copy::reflink(p, q)).or_else(|err| {
+                if !noticed.replace(true) {
+                    println!("can't reflink ({err}), copying instead");
+                }
+                transfer.copy(p, q, &progress)
+            })
+        })
+    }
+    /// Hard link files to a new location within the same file system; directories are recreated.
+    pub fn hard_link(medias: &mut Vec<impl SourceEntry + NewEntry>) {
+        files_op(medias, "hardlink", silent, |p, q| {
+            link_tree(p, q, |p, q| fs::hard_link(p, q))
+        })
+    }
+    /// Create symbolic links in a new location, pointing to the absolute paths of the files.
+    pub fn symlink(medias: &mut Vec<impl SourceEntry + NewEntry>) {
+        files_op(medias, "symlink", silent, |p, q| {
+            symlink(&std::path::absolute(p)?, q)
+        })
+    }
+}
+
+/// How files are copied, which also applies to moves across file systems.
+#[derive(Debug, Args)]
+pub struct Transfer {
+    #[command(flatten)]
+    pub trash: Trash,
+    /// Verify each copy byte by byte against its source before completing it, besides its size.
+    #[arg(long)]
+    verify: bool,
+}
+
+/// Where removed files go, which by default is the trash, so they can be recovered.
+#[derive(Debug, Args)]
+pub struct Trash {
+    /// Remove files permanently, instead of moving them to the trash.
+    #[arg(long, help_heading = Some("Removal"))]
+    permanent: bool,
+    /// The trash directory to use when the system one is not available.
+    #[arg(long, value_name = "PATH", conflicts_with = "permanent", help_heading = Some("Removal"))]
+    trash_dir: Option<PathBuf>,
+}
+
+impl Trash {
+    /// Remove a file or directory, moving it to the trash unless removal is permanent.
+    ///
+    /// It uses the freedesktop.org trash of the file system the path is in, the user trash on
+    /// macOS, or the fallback trash directory, which also follows the freedesktop.org layout.
+    pub fn remove(&self, path: &Path) -> io::Result<()> {
+        self.remove_as(path, path)
+    }
+
+    /// Remove a file or directory that was moved aside, recording its original path in the trash.
+    pub fn remove_as(&self, path: &Path, original: &Path) -> io::Result<()> {
+        if self.permanent {
+            return match path.is_dir() {
+                true => fs::remove_dir_all(path),
+                false => fs::remove_file(path),
+            };
+        }
+        let (path, original) = (std::path::absolute(path)?, std::path::absolute(original)?);
+        let system = match cfg!(test) {
+            true => None, // tests must not touch the user's trash, they use the fallback one.
+            false => system_trash(&path, &original),
+        };
+        match (system, &self.trash_dir) {
+            (Some(Ok(())), _) => Ok(()),
+            (_, Some(dir)) => trash_into(&std::path::absolute(dir)?, None, &path, &original),
+            (Some(Err(err)), None) => Err(err),
+            (None, None) => Err(io::Error::other(
+                "no trash available, use --trash-dir or --permanent",
+            )),
+        }
+    }
+}
+
+/// Move a path to a freedesktop.org trash directory, i.e. into its `files` directory, with the
+/// original location in the `info` directory, relative to `top` if given.
+fn trash_into(trash: &Path, top: Option<&Path>, path: &Path, original: &Path) -> io::Result<()> {
+    let (files, info) = (trash.join("files"), trash.join("info"));
+    [&files, &info]
+        .into_iter()
+        .try_for_each(create_private_dir)?;
+
+    // step: reserve a unique name by creating both its info file and a placeholder in files, which
+    // are atomic, so neither other trashed files nor orphans there are ever overwritten.
+    let name = original
+        .file_name()
+        .and_then(|n| n.to_str())
+        .ok_or_else(|| io::Error::other("invalid file name"))?;
+    let (stem, ext) = match name.rsplit_once('.') {
+        Some((stem, ext)) if !stem.is_empty() => (stem, format!(".{ext}")),
+        _ => (name, String::new()),
+    };
+    let is_dir = path.symlink_metadata()?.is_dir();
+    let placeholder = |dest: &Path| match is_dir {
+        true => fs::create_dir(dest),
+        false => fs::File::create_new(dest).map(drop),
+    };
+    let (unique, mut file) = (1..)
+        .map(|i| match i {
+            1 => name.to_owned(),
+            _ => format!("{stem}.{i}{ext}"),
+        })
+        .find_map(|unique| {
+            let info = info.join(format!("{unique}.trashinfo"));
+            let res = fs::File::create_new(&info).and_then(|file| {
+                placeholder(&files.join(&unique))
+                    .map(|()| file)
+                    .inspect_err(|_| {
+                        let _ = fs::remove_file(&info);
+                    })
+            });
+            match res {
+                Err(err) if err.kind() == io::ErrorKind::AlreadyExists => None,
+                res => Some(res.map(|file| (unique, file))),
+            }
+        })
+        .unwrap()?;
+
+    // step: record where it came from, and finally move it.
+    let original = top
+        .and_then(|t| original.strip_prefix(t).ok())
+        .unwrap_or(original);
+    let date = chrono::Local::now().format("%Y-%m-%dT%H:%M:%S");
+    let text = format!(
+        "[Trash Info]\nPath={}\nDeletionDate={date}\n",
+        percent_encode(original)
+    );
+    let (info, dest) = (
+        info.join(format!("{unique}.trashinfo")),
+        files.join(&unique),
+    );
+    file.write_all(text.as_bytes())
+        .and_then(|()| move_into(path, &dest, is_dir))
+        .inspect_err(|_| {
+            // the info is kept if the original was partially moved, so it can still be restored.
+            let res = match is_dir {
+                true => fs::remove_dir(&dest),
+                false => fs::remove_file(&dest),
+            };
+            if res.is_ok() {
+                let _ = fs::remove_file(&info);
+            }
+        })
+}
+
+/// Move a path to its reserved place in a trash, replacing the placeholder, or copy it there and
+/// remove the original, if the trash is in another file system.
+fn move_into(path: &Path, dest: &Path, is_dir: bool) -> io::Result<()> {
+    if cfg!(windows) && is_dir {
+        fs::remove_dir(dest)?; // it can't replace directories.
+    }
+    match fs::rename(path, dest) {
+        Err(err) if err.kind() == io::ErrorKind::CrossesDevices => {}
+        res => return res,
+    }
+    let copy = |p: &Path, q: &Path| {
+        fs::copy(p, q)?;
+        preserve(&p.metadata()?, q)
+    };
+    match is_dir {
+        true => {
+            let _ = fs::remove_dir(dest); // the placeholder, which is recreated with the copies.
+            link_tree(path, dest, copy)?;
+            preserve(&path.metadata()?, dest)?;
+            fs::remove_dir_all(path)
+        }
+        false => copy(path, dest).and_then(|()| fs::remove_file(path)),
+    }
+}
+
+/// Find the trash of the file system the path is in, and move the path there.
+#[cfg(target_os = "linux")]
+fn system_trash(path: &Path, original: &Path) -> Option<io::Result<()>> {
+    use std::env;
+    use std::os::unix::fs::{MetadataExt, PermissionsExt};
+
+    let dev = |p: &Path| p.symlink_metadata().map(|m| m.dev()).ok();
+    let src_dev = dev(path)?;
+
+    // step: the home trash, if it is in the same file system.
+    let data = env::var_os("XDG_DATA_HOME")
+        .filter(|d| !d.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|h| PathBuf::from(h).join(".local/share")));
+    if let Some(data) = data {
+        let home_dev = data.ancestors().find_map(dev);
+        if home_dev == Some(src_dev) {
+            return Some(trash_into(&data.join("Trash"), None, path, original));
+        }
     }
+
+    // step: the top directory trash, i.e. at the mount point of the path.
+    let top = path
+        .ancestors()
+        .skip(1)
+        .take_while(|p| dev(p) == Some(src_dev))
+        .last()?;
+    let uid = fs::metadata("/proc/self").ok()?.uid();
+    let shared = top.join(".Trash");
+    let valid = shared.symlink_metadata().is_ok_and(|m| {
+        m.is_dir() && m.permissions().mode() & 0o1000 != 0 // must have the sticky bit.
+    });
+    let trash = match valid {
+        true => shared.join(uid.to_string()),
+        false => top.join(format!(".Trash-{uid}")),
+    };
+    Some(trash_into(&trash, Some(top), path, original))
+}
+
+/// Move the path to the user trash, if it is in the same file system.
+#[cfg(target_os = "macos")]
+fn system_trash(path: &Path, original: &Path) -> Option<io::Result<()>> {
+    use std::os::unix::fs::MetadataExt;
+
+    let trash = PathBuf::from(std::env::var_os("HOME")?).join(".Trash");
+    let (src, dst) = (path.symlink_metadata().ok()?, trash.metadata().ok()?);
+    if src.dev() != dst.dev() {
+        return None;
+    }
+    let name = original.file_name()?.to_str()?;
+    let target = (1..)
+        .map(|i| match i {
+            1 => trash.join(name),
+            _ => trash.join(format!("{name} {i}")),
+        })
+        .find(|t| !t.exists())?;
+    Some(fs::rename(path, target))
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+fn system_trash(_: &Path, _: &Path) -> Option<io::Result<()>> {
+    None
+}
+
+fn create_private_dir(dir: &PathBuf) -> io::Result<()> {
+    let mut builder = fs::DirBuilder::new();
+    builder.recursive(true);
+    #[cfg(unix)]
+    std::os::unix::fs::DirBuilderExt::mode(&mut builder, 0o700);
+    builder.create(dir)
+}
+
+/// Encode a path like an URI, as the trash info files require.
+fn percent_encode(path: &Path) -> String {
+    path.as_os_str()
+        .as_encoded_bytes()
+        .iter()
+        .fold(String::new(), |mut acc, &b| {
+            match b {
+                b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' | b'/' => {
+                    acc.push(b as char)
+                }
+                _ => acc.push_str(&format!("%{b:02X}")),
+            }
+            acc
+        })
 }
 
 fn files_op(
     paths: &mut Vec<impl SourceEntry + NewEntry>,
+    name: &str,
     notify: fn(&[u8]),
-    op: fn(&Path, &Path) -> io::Result<()>,
+    op: impl Fn(&Path, &Path) -> io::Result<()>,
 ) {
     paths.retain(|m| {
+        if !utils::is_running() {
+            return true; // the remaining ones are kept, and reported once by the caller.
+        }
         let target = m.new_entry();
-        if target.exists() {
+        let case_only = is_case_only(m.src_entry(), &target);
+        if target.exists() && !case_only {
             notify(b"-\n");
             eprintln!("error: file already exists: {} -> {target}", m.src_entry());
             notify(b"\n");
             return true;
         }
-        match op(m.src_entry().as_ref(), target.as_ref()) {
+        let res = match case_only {
+            // case-insensitive file systems see the target as the source itself, so go through a temp name.
+            true => {
+                let temp = temp_name(&target);
+                op(m.src_entry().as_ref(), temp.as_ref()).and_then(|()| fs::rename(&temp, &target))
+            }
+            false => op(m.src_entry().as_ref(), target.as_ref()),
+        };
+        match res {
             Ok(()) => false,
+            Err(_) if !utils::is_running() => true, // interrupted by the abort.
             Err(err) => {
                 notify(b"x\n");
-                eprintln!("error: {err}: {} -> {target}", m.src_entry());
+                eprintln!("error: {name}: {err}: {} -> {target}", m.src_entry());
                 notify(b"\n");
                 true
             }
@@ -47,44 +365,324 @@ fn files_op(
     notify(b"\n");
 }
 
-// `n` is just a counter for verbose output.
-fn copy_path(p: &Path, q: &Path, remove_dir: bool, n: usize) -> io::Result<()> {
-    if p.is_dir() {
-        fs::create_dir(q).and_then(|()| {
-            verbose(b"d[");
-            let files = fs::read_dir(p)?
-                .flatten()
-                .try_fold(Vec::new(), |mut acc, de| {
-                    let is_dir = de.path().is_dir(); // need to cache because is_dir goes to the fs again, and copy_path may have removed it.
-                    copy_path(&de.path(), &q.join(de.file_name()), remove_dir, n + 1).map(|()| {
-                        if !is_dir {
-                            verbose(b".");
-                            if remove_dir {
-                                acc.push(de.path())
-                            }
-                        }
-                        acc
-                    })
-                });
-            verbose(b"]");
-            if remove_dir {
-                files
-                    .and_then(|files| files.iter().try_for_each(fs::remove_file))
-                    .and_then(|()| fs::remove_dir(p))
-            } else {
-                files.map(|_| ())
+/// The temporary name an entry is moved aside to, in its same directory.
+pub fn temp_name(entry: &Entry) -> Entry {
+    entry.with_file_name(format!("__refine+{}__", entry.file_name()))
+}
+
+/// Sort the renames so the ones whose targets are sources of others come after them, and move one
+/// file of each cycle to a temporary name, returning these names by source path.
+fn order_renames<M: SourceEntry + NewEntry>(medias: &mut Vec<M>) -> HashMap<PathBuf, Entry> {
+    let targets = medias.iter().map(M::new_entry).collect::<Vec<_>>();
+    let srcs = medias.iter().map(M::src_entry).collect::<Vec<_>>();
+    let (order, cycles) = dependency_order(&srcs, &targets.iter().collect::<Vec<_>>());
+
+    // step: move the cycle breakers out of the way, so the rest of their cycles can proceed.
+    let temps = cycles
+        .into_iter()
+        .filter_map(|k| {
+            let src = medias[k].src_entry();
+            let temp = temp_name(src);
+            match fs::rename(src, &temp) {
+                Ok(()) => Some((src.to_path_buf(), temp)),
+                Err(err) => {
+                    eprintln!("error: {err}: {src} -> {temp}");
+                    None
+                }
             }
         })
-    } else if n == 0 {
-        fs::copy(p, q).and_then(|_| {
-            verbose(b".");
-            if remove_dir {
-                fs::remove_file(p)?
+        .collect();
+
+    let mut slots = std::mem::take(medias)
+        .into_iter()
+        .map(Some)
+        .collect::<Vec<_>>();
+    *medias = order
+        .into_iter()
+        .map(|k| slots[k].take().unwrap())
+        .collect();
+    temps
+}
+
+/// Find an order where each source is vacated before it is used as a target, along with one item of
+/// each cycle, which must be moved out of the way first.
+///
+/// Since sources are unique, each item depends on at most one other, so dependencies can only form
+/// chains and simple cycles; the original order is otherwise kept.
+fn dependency_order<T: Hash + Eq>(srcs: &[T], targets: &[T]) -> (Vec<usize>, Vec<usize>) {
+    #[derive(Clone, Copy, PartialEq)]
+    enum State {
+        New,
+        Visiting,
+        Done,
+    }
+
+    let index = srcs.iter().zip(0..).collect::<HashMap<_, _>>();
+    let deps = targets
+        .iter()
+        .zip(0..)
+        .map(|(t, i)| index.get(t).copied().filter(|&j| j != i))
+        .collect::<Vec<_>>();
+
+    // step: follow each chain of dependencies, placing the deepest ones first.
+    let mut state = vec![State::New; srcs.len()];
+    let (mut order, mut cycles) = (Vec::with_capacity(srcs.len()), vec![]);
+    for i in 0..srcs.len() {
+        let (mut chain, mut next) = (vec![], Some(i));
+        while let Some(k) = next.filter(|&k| state[k] == State::New) {
+            state[k] = State::Visiting;
+            chain.push(k);
+            next = deps[k];
+        }
+        if let Some(k) = next.filter(|&k| state[k] == State::Visiting) {
+            cycles.push(k); // the chain closed on itself, so k's target is only vacated by the last one.
+        }
+        chain.iter().for_each(|&k| state[k] = State::Done);
+        order.extend(chain.into_iter().rev());
+    }
+    (order, cycles)
+}
+
+/// Recreate a directory tree in a new location, linking or cloning its files with `link`.
+///
+/// If any of them fails, the new tree is removed, so the operation can be retried in another way.
+fn link_tree(p: &Path, q: &Path, link: fn(&Path, &Path) -> io::Result<()>) -> io::Result<()> {
+    fn contents(p: &Path, q: &Path, link: fn(&Path, &Path) -> io::Result<()>) -> io::Result<()> {
+        fs::read_dir(p)?.try_for_each(|de| {
+            let de = de?;
+            let (src, dst) = (de.path(), q.join(de.file_name()));
+            match src.is_dir() {
+                true => fs::create_dir(&dst).and_then(|()| contents(&src, &dst, link)),
+                false => link(&src, &dst),
             }
-            Ok(())
         })
-    } else {
-        fs::copy(p, q).map(|_| ()) // this is called recursively by the is_dir case above.
+    }
+
+    if !p.is_dir() {
+        return link(p, q);
+    }
+    fs::create_dir(q)?; // only remove it on failure if it was created here.
+    contents(p, q, link).inspect_err(|_| {
+        let _ = fs::remove_dir_all(q);
+    })
+}
+
+#[cfg(unix)]
+fn symlink(p: &Path, q: &Path) -> io::Result<()> {
+    std::os::unix::fs::symlink(p, q)
+}
+
+#[cfg(windows)]
+fn symlink(p: &Path, q: &Path) -> io::Result<()> {
+    match p.is_dir() {
+        true => std::os::windows::fs::symlink_dir(p, q),
+        false => std::os::windows::fs::symlink_file(p, q),
+    }
+}
+
+/// Check whether the target only differs by case from the source, and is the source itself as seen
+/// by a case-insensitive file system.
+pub(super) fn is_case_only(src: &Entry, target: &Entry) -> bool {
+    src.parent() == target.parent()
+        && src.file_name() != target.file_name()
+        && src.file_name().to_lowercase() == target.file_name().to_lowercase()
+        && is_same_file(src, target)
+        && !is_listed(target) // a hard link with that exact name is another entry.
+}
+
+/// Check whether the exact name of the entry is in its parent directory.
+fn is_listed(entry: &Entry) -> bool {
+    let Some(Ok(mut rd)) = entry.parent().map(fs::read_dir) else {
+        return false;
+    };
+    rd.any(|de| de.is_ok_and(|de| de.file_name() == entry.file_name()))
+}
+
+impl Transfer {
+    /// Copy a file or directory through a partial name, which is only renamed when complete, so
+    /// interrupted copies can be resumed.
+    fn copy(&self, p: &Path, q: &Path, progress: &Progress) -> io::Result<()> {
+        let part = partial(q);
+        match p.is_dir() {
+            true => self.copy_dir(p, &part, progress)?,
+            false => self.copy_file(p, &part, progress)?,
+        }
+        fs::rename(&part, q)
+    }
+
+    fn copy_dir(&self, p: &Path, q: &Path, progress: &Progress) -> io::Result<()> {
+        fs::create_dir_all(q)?;
+        for de in fs::read_dir(p)? {
+            let de = de?;
+            let (src, dst) = (de.path(), q.join(de.file_name()));
+            if src.is_dir() {
+                self.copy_dir(&src, &dst, progress)?;
+                continue;
+            }
+            let md = src.metadata()?;
+            match dst.metadata() {
+                // completed by a previous run, since the times are only set at the end.
+                Ok(d) if d.len() == md.len() && d.modified()? == md.modified()? => {
+                    progress.skip(md.len())
+                }
+                _ => {
+                    let part = partial(&dst);
+                    self.copy_file(&src, &part, progress)?;
+                    fs::rename(&part, &dst)?;
+                }
+            }
+        }
+        preserve(&p.metadata()?, q)
+    }
+
+    fn copy_file(&self, p: &Path, q: &Path, progress: &Progress) -> io::Result<()> {
+        let md = p.metadata()?;
+        let mut src = File::open(p)?;
+
+        // step: resume a previous partial copy, if it still matches the source.
+        let offset = resume_offset(&mut src, q, &md)?;
+        let mut dst = match offset {
+            0 => File::create(q)?,
+            _ => OpenOptions::new().append(true).open(q)?,
+        };
+        src.seek(SeekFrom::Start(offset))?;
+        progress.skip(offset);
+
+        // step: copy the remaining data.
+        let mut buf = vec![0; BUF_SIZE];
+        loop {
+            if !utils::is_running() {
+                return Err(io::Error::new(io::ErrorKind::Interrupted, "aborted"));
+            }
+            let n = src.read(&mut buf)?;
+            if n == 0 {
+                break;
+            }
+            dst.write_all(&buf[..n])?;
+            progress.advance(n as u64);
+        }
+        dst.sync_all()?;
+        drop(dst);
+
+        // step: verify the copy before it can be completed.
+        if q.metadata()?.len() != md.len() {
+            return Err(io::Error::other("size mismatch after copy"));
+        }
+        if self.verify && !same_content(p, q) {
+            fs::remove_file(q)?; // it can't be resumed.
+            return Err(io::Error::other("content mismatch after copy"));
+        }
+        preserve(&md, q)
+    }
+}
+
+const BUF_SIZE: usize = 1 << 20;
+
+/// The name of a copy in progress, which is hidden so it is not fetched.
+fn partial(path: &Path) -> PathBuf {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    path.with_file_name(format!(".{name}.refine-part"))
+}
+
+/// Find where to resume a partial copy; it must not be larger nor older than the source, and its
+/// last block must match the source.
+fn resume_offset(src: &mut File, part: &Path, md: &fs::Metadata) -> io::Result<u64> {
+    let Ok(pmd) = part.metadata() else {
+        return Ok(0);
+    };
+    if pmd.len() == 0 || pmd.len() > md.len() || pmd.modified()? < md.modified()? {
+        return Ok(0);
+    }
+    let n = pmd.len().min(BUF_SIZE as u64);
+    let tail = |f: &mut File| -> io::Result<Vec<u8>> {
+        let mut buf = vec![0; n as usize];
+        f.seek(SeekFrom::Start(pmd.len() - n))?;
+        f.read_exact(&mut buf)?;
+        Ok(buf)
+    };
+    match tail(src)? == tail(&mut File::open(part)?)? {
+        true => Ok(pmd.len()),
+        false => Ok(0),
+    }
+}
+
+/// Preserve the modification time and permissions of the source.
+fn preserve(md: &fs::Metadata, path: &Path) -> io::Result<()> {
+    let file = match md.is_dir() {
+        true => File::open(path)?,
+        false => OpenOptions::new().write(true).open(path)?,
+    };
+    file.set_modified(md.modified()?)?;
+    fs::set_permissions(path, md.permissions())
+}
+
+/// A byte-level progress bar for copies, with throughput and ETA.
+struct Progress {
+    total: u64,
+    done: Cell<u64>,
+    skipped: Cell<u64>,
+    start: Instant,
+    last: Cell<Instant>,
+}
+
+impl Progress {
+    fn new(medias: &[impl SourceEntry]) -> Progress {
+        fn size(path: &Path) -> u64 {
+            match path.is_dir() {
+                true => fs::read_dir(path)
+                    .map(|rd| rd.flatten().map(|de| size(&de.path())).sum())
+                    .unwrap_or_default(),
+                false => path.metadata().map(|md| md.len()).unwrap_or_default(),
+            }
+        }
+
+        let now = Instant::now();
+        Progress {
+            total: medias.iter().map(|m| size(m.src_entry().as_ref())).sum(),
+            done: Cell::new(0),
+            skipped: Cell::new(0),
+            start: now,
+            last: now.into(),
+        }
+    }
+
+    /// Account for bytes that didn't need to be copied, which do not count for throughput.
+    fn skip(&self, n: u64) {
+        self.skipped.set(self.skipped.get() + n);
+        self.advance(n);
+    }
+
+    fn advance(&self, n: u64) {
+        self.done.set(self.done.get() + n);
+        let now = Instant::now();
+        if now - self.last.get() >= Duration::from_millis(100) || self.done.get() >= self.total {
+            self.last.set(now);
+            self.render();
+        }
+    }
+
+    fn render(&self) {
+        const WIDTH: usize = 30;
+        let done = self.done.get();
+        let frac = match self.total {
+            0 => 1.,
+            total => (done as f64 / total as f64).min(1.),
+        };
+        let copied = (done - self.skipped.get()) as f64;
+        let rate = copied / self.start.elapsed().as_secs_f64().max(1e-3);
+        let eta = (self.total.saturating_sub(done) as f64 / rate.max(1.)).ceil();
+        let bar = "=".repeat((frac * WIDTH as f64) as usize);
+        verbose(
+            format!(
+                "\r[{bar:<WIDTH$}] {:>3.0}% {} of {}, {}, ETA {}   ",
+                frac * 100.,
+                done.human_count_bytes(),
+                self.total.human_count_bytes(),
+                rate.human_throughput_bytes(),
+                eta.human_duration(),
+            )
+            .as_bytes(),
+        );
     }
 }
 
@@ -93,3 +691,219 @@ fn verbose(c: &[u8]) {
     io::stdout().write_all(c).unwrap();
     io::stdout().flush().unwrap();
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{Op, Scratch};
+    use std::time::UNIX_EPOCH;
+
+    fn transfer(verify: bool) -> Transfer {
+        let trash = Trash {
+            permanent: true,
+            trash_dir: None,
+        };
+        Transfer { trash, verify }
+    }
+
+    /// Set the modification time of a file, in seconds since the epoch.
+    fn touch(path: &Path, secs: u64) {
+        let file = OpenOptions::new().write(true).open(path).unwrap();
+        file.set_modified(UNIX_EPOCH + Duration::from_secs(secs))
+            .unwrap();
+    }
+
+    #[test]
+    fn renames_order() {
+        #[track_caller]
+        fn case(renames: &[(&str, &str)], order: &[usize], cycles: &[usize]) {
+            let (srcs, targets) = renames.iter().copied().unzip::<_, _, Vec<_>, Vec<_>>();
+            assert_eq!(
+                dependency_order(&srcs, &targets),
+                (order.to_vec(), cycles.to_vec())
+            );
+        }
+
+        case(&[("a", "x"), ("b", "y")], &[0, 1], &[]);
+        case(&[("a", "b"), ("b", "c")], &[1, 0], &[]);
+        case(&[("a", "b"), ("b", "c"), ("c", "d")], &[2, 1, 0], &[]);
+        case(&[("c", "d"), ("a", "b"), ("b", "c")], &[0, 2, 1], &[]);
+        case(&[("a", "b"), ("b", "a")], &[1, 0], &[0]);
+        case(&[("a", "b"), ("b", "c"), ("c", "a")], &[2, 1, 0], &[0]);
+        case(&[("x", "y"), ("b", "a"), ("a", "b")], &[0, 2, 1], &[1]);
+        case(&[("a", "a"), ("b", "a")], &[0, 1], &[]);
+    }
+
+    #[test]
+    fn trash_paths() {
+        #[track_caller]
+        fn case(path: &str, out: &str) {
+            assert_eq!(percent_encode(Path::new(path)), out);
+        }
+
+        case("/home/user/a.txt", "/home/user/a.txt");
+        case("/media/My Photos/b~1.jpg", "/media/My%20Photos/b~1.jpg");
+        case("rel/ção#1", "rel/%C3%A7%C3%A3o%231");
+    }
+
+    #[test]
+    fn trash_names() {
+        let dir = Scratch::new("ops-trash");
+        dir.file("trash/files/a.txt", "orphan")
+            .file("trash/info/a.2.txt.trashinfo", "")
+            .file("a.txt", "first")
+            .file("d/b.txt", "b");
+        let trash = dir.join("trash");
+        let remove = |name: &str| trash_into(&trash, None, &dir.join(name), &dir.join(name));
+
+        remove("a.txt").unwrap();
+        dir.file("a.txt", "second");
+        remove("a.txt").unwrap();
+        remove("d").unwrap();
+        assert!(remove("nope").is_err());
+        assert_eq!(
+            dir.files(),
+            [
+                "trash/files/a.3.txt",
+                "trash/files/a.4.txt",
+                "trash/files/a.txt",
+                "trash/files/d/b.txt",
+                "trash/info/a.2.txt.trashinfo",
+                "trash/info/a.3.txt.trashinfo",
+                "trash/info/a.4.txt.trashinfo",
+                "trash/info/d.trashinfo",
+            ]
+        );
+        let read = |name: &str| fs::read_to_string(trash.join(name)).unwrap();
+        assert_eq!(read("files/a.txt"), "orphan");
+        assert_eq!(read("files/a.3.txt"), "first");
+        assert_eq!(read("files/a.4.txt"), "second");
+        let path = percent_encode(&dir.join("a.txt"));
+        assert!(read("info/a.4.txt.trashinfo").contains(&format!("\nPath={path}\n")));
+    }
+
+    #[test]
+    fn case_only() {
+        let dir = Scratch::new("ops-case-only");
+        dir.file("a.txt", "abc").file("l.txt", "abc");
+        let insensitive = fs::hard_link(dir.join("l.txt"), dir.join("L.txt")).is_err(); // it already "exists" there.
+
+        assert_eq!(
+            is_case_only(&dir.entry("a.txt"), &dir.entry("A.txt")),
+            insensitive
+        );
+        assert_eq!(
+            is_case_only(&dir.entry("l.txt"), &dir.entry("L.txt")),
+            insensitive
+        );
+        assert!(!is_case_only(&dir.entry("a.txt"), &dir.entry("a.txt")));
+        assert!(!is_case_only(&dir.entry("a.txt"), &dir.entry("l.txt")));
+
+        // step: a case-only rename goes through a temp name, while a hard link is another file.
+        let mut medias = vec![
+            Op(dir.entry("a.txt"), dir.entry("A.txt")),
+            Op(dir.entry("l.txt"), dir.entry("L.txt")),
+        ];
+        FileOps::rename_move(&mut medias);
+        assert_eq!(medias.len(), usize::from(!insensitive));
+        match insensitive {
+            true => assert_eq!(dir.files(), ["A.txt", "L.txt"]),
+            false => assert_eq!(dir.files(), ["A.txt", "L.txt", "l.txt"]),
+        }
+        assert_eq!(fs::read_to_string(dir.join("A.txt")).unwrap(), "abc");
+    }
+
+    #[test]
+    fn reflink_or_copy() {
+        let dir = Scratch::new("ops-reflink");
+        dir.file("a.txt", "abc").file("d/x.txt", "xyz");
+        let transfer = transfer(true);
+        let mut medias = vec![
+            Op(dir.entry("a.txt"), dir.entry("b.txt")),
+            Op(dir.entry("d"), dir.entry("e")),
+        ];
+        FileOps::reflink(&mut medias, &transfer); // either cloned or copied, depending on the file system.
+        assert!(medias.is_empty());
+        assert_eq!(dir.files(), ["a.txt", "b.txt", "d/x.txt", "e/x.txt"]);
+        assert_eq!(fs::read_to_string(dir.join("b.txt")).unwrap(), "abc");
+        assert_eq!(fs::read_to_string(dir.join("e/x.txt")).unwrap(), "xyz");
+    }
+
+    #[test]
+    fn resume_offsets() {
+        #[track_caller]
+        fn case(part: Option<(&str, u64)>, out: u64) {
+            let dir = Scratch::new("ops-resume");
+            dir.file("a.txt", "abcdef");
+            touch(&dir.join("a.txt"), 10);
+            if let Some((data, mtime)) = part {
+                dir.file("part", data);
+                touch(&dir.join("part"), mtime);
+            }
+            let path = dir.join("a.txt");
+            let offset = resume_offset(
+                &mut File::open(&path).unwrap(),
+                &dir.join("part"),
+                &path.metadata().unwrap(),
+            );
+            assert_eq!(offset.unwrap(), out);
+        }
+
+        case(None, 0);
+        case(Some(("abc", 20)), 3);
+        case(Some(("abcdef", 10)), 6);
+        case(Some(("", 20)), 0);
+        case(Some(("abx", 20)), 0); // the source changed.
+        case(Some(("abc", 5)), 0); // the source was modified after the part.
+        case(Some(("abcdefgh", 20)), 0); // the source shrank.
+    }
+
+    #[test]
+    fn copy_resume() {
+        #[track_caller]
+        fn case(part: &str, mtime: u64, verify: bool, skipped: u64) {
+            let dir = Scratch::new("ops-copy");
+            dir.file("a.txt", "abcdef").file(".b.txt.refine-part", part);
+            touch(&dir.join("a.txt"), 10);
+            touch(&dir.join(".b.txt.refine-part"), mtime);
+            let (src, dst) = (dir.join("a.txt"), dir.join("b.txt"));
+            let progress = Progress::new(&[Op(dir.entry("a.txt"), dir.entry("b.txt"))]);
+            transfer(verify).copy(&src, &dst, &progress).unwrap();
+
+            // step: the part is renamed to the final name, with the source's mtime.
+            assert_eq!(dir.files(), ["a.txt", "b.txt"]);
+            assert_eq!(fs::read_to_string(&dst).unwrap(), "abcdef");
+            let mtime = |p: &Path| p.metadata().unwrap().modified().unwrap();
+            assert_eq!(mtime(&dst), mtime(&src));
+            assert_eq!(progress.skipped.get(), skipped);
+        }
+
+        case("abc", 20, false, 3); // resumed.
+        case("abc", 20, true, 3);
+        case("abc", 5, false, 0); // restarted, the source is newer.
+        case("abx", 20, false, 0); // restarted, the source changed.
+        case("abcdefgh", 20, false, 0); // restarted, the source shrank.
+    }
+
+    #[test]
+    fn copy_verify() {
+        // a part larger than the block checked on resume, whose start no longer matches.
+        let dir = Scratch::new("ops-verify");
+        let data = "x".repeat(BUF_SIZE + 10);
+        dir.file("a.txt", &data).file(
+            ".b.txt.refine-part",
+            &format!("y{}", &data[1..BUF_SIZE + 1]),
+        );
+        touch(&dir.join("a.txt"), 10);
+        let (src, dst) = (dir.join("a.txt"), dir.join("b.txt"));
+        let progress = Progress::new(&[Op(dir.entry("a.txt"), dir.entry("b.txt"))]);
+
+        let err = transfer(true).copy(&src, &dst, &progress).unwrap_err();
+        assert_eq!(err.to_string(), "content mismatch after copy");
+        assert_eq!(dir.files(), ["a.txt"]); // the part is removed, so it can't be resumed.
+
+        // step: a new attempt starts over.
+        transfer(true).copy(&src, &dst, &progress).unwrap();
+        assert_eq!(fs::read_to_string(&dst).unwrap(), data);
+    }
+}
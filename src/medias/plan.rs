@@ -0,0 +1,378 @@
+use super::{FileOps, NewEntry, SourceEntry, Transfer, ops};
+use crate::entries::Entry;
+use anyhow::{Context, Result, anyhow};
+use chrono::Local;
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+use toml::{Table, Value};
+
+/// The kind of file operation in a plan, mirroring [FileOps].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OpKind {
+    /// Rename or move within the same file system.
+    Rename,
+    /// Move, even across file systems.
+    Move,
+    /// Copy, even across file systems.
+    Copy,
+    /// Clone with copy-on-write, falling back to a copy.
+    Reflink,
+    /// Hard link within the same file system.
+    Hardlink,
+    /// Symbolic link.
+    Symlink,
+}
+
+/// A saved plan, i.e. the exact list of operations a command would apply.
+#[derive(Debug)]
+pub struct Plan {
+    /// The command that created the plan.
+    pub command: String,
+    pub ops: Vec<PlanOp>,
+}
+
+/// A single operation of a plan, along with the state of its source when it was planned.
+#[derive(Debug)]
+pub struct PlanOp {
+    kind: OpKind,
+    src: PathBuf,
+    dst: PathBuf,
+    is_dir: bool,
+    size: Option<u64>,
+    mtime: Option<i64>,
+}
+
+/// A validated operation, ready to be applied.
+#[derive(Debug)]
+pub struct ReadyOp {
+    kind: OpKind,
+    entry: Entry,
+    target: Entry,
+}
+
+impl Plan {
+    /// Save the changes of a command to a plan file, with absolute paths.
+    pub fn save(
+        path: &Path,
+        command: &str,
+        kind: OpKind,
+        medias: &[impl SourceEntry + NewEntry],
+    ) -> Result<()> {
+        let ops = medias
+            .iter()
+            .map(|m| {
+                let (src, dst) = (m.src_entry().resolve()?, m.new_entry().resolve()?);
+                let (size, mtime) = match src.is_dir() {
+                    true => (None, None), // directories change while their contents are processed.
+                    false => state(&src)?,
+                };
+                let mut op = Table::new();
+                op.insert("kind".into(), kind.as_str().into());
+                op.insert("src".into(), src.to_str().into());
+                op.insert("dst".into(), dst.to_str().into());
+                if src.is_dir() {
+                    op.insert("dir".into(), true.into());
+                }
+                if let (Some(size), Some(mtime)) = (size, mtime) {
+                    op.insert("size".into(), (size as i64).into());
+                    op.insert("mtime".into(), mtime.into());
+                }
+                Ok(Value::Table(op))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let mut plan = Table::new();
+        plan.insert("command".into(), command.into());
+        plan.insert("created".into(), Local::now().to_rfc3339().into());
+        plan.insert("op".into(), Value::Array(ops));
+        let text = format!("# refine plan, run it with `refine apply <FILE>`.\n{plan}");
+        fs::write(path, text).with_context(|| format!("writing {path:?}"))?;
+        println!("plan saved: {} operations to {path:?}", medias.len());
+        Ok(())
+    }
+
+    /// Load a plan file.
+    pub fn load(path: &Path) -> Result<Plan> {
+        let text = fs::read_to_string(path).with_context(|| format!("reading {path:?}"))?;
+        let plan = text
+            .parse::<Table>()
+            .with_context(|| format!("parsing {path:?}"))?;
+        let command = match plan.get("command") {
+            Some(Value::String(s)) => s.to_owned(),
+            _ => return Err(anyhow!("missing command in plan {path:?}")),
+        };
+        let ops = match plan.get("op") {
+            Some(Value::Array(ops)) => ops.iter().zip(1..).map(|(op, n)| {
+                PlanOp::parse(op).with_context(|| format!("invalid operation {n} in {path:?}"))
+            }),
+            None => {
+                return Ok(Plan {
+                    command,
+                    ops: vec![],
+                });
+            }
+            _ => return Err(anyhow!("invalid operations in plan {path:?}")),
+        };
+        let ops = ops.collect::<Result<_>>()?;
+        Ok(Plan { command, ops })
+    }
+
+    /// Validate that every source is unchanged and every target is free, returning the ready
+    /// operations and the stale ones along with the reason.
+    pub fn validate(self) -> (Vec<ReadyOp>, Vec<(PlanOp, String)>) {
+        // targets that are sources of other operations will be free by then.
+        let sources = self.ops.iter().map(|op| &op.src).collect::<HashSet<_>>();
+        let (mut ready, mut stale) = (vec![], vec![]);
+        let checked = self
+            .ops
+            .iter()
+            .map(|op| op.check(&sources))
+            .collect::<Vec<_>>();
+        for (op, res) in self.ops.into_iter().zip(checked) {
+            match res {
+                Ok((entry, target)) => ready.push(ReadyOp {
+                    kind: op.kind,
+                    entry,
+                    target,
+                }),
+                Err(err) => stale.push((op, err.to_string())),
+            }
+        }
+        (ready, stale)
+    }
+}
+
+impl PlanOp {
+    fn parse(op: &Value) -> Result<PlanOp> {
+        let op = op.as_table().ok_or_else(|| anyhow!("not a table"))?;
+        let string = |key| match op.get(key) {
+            Some(Value::String(s)) => Ok(s.as_str()),
+            _ => Err(anyhow!("missing {key}")),
+        };
+        let int = |key| op.get(key).and_then(Value::as_integer);
+        let kind = match string("kind")? {
+            "rename" => OpKind::Rename,
+            "move" => OpKind::Move,
+            "copy" => OpKind::Copy,
+            "reflink" => OpKind::Reflink,
+            "hardlink" => OpKind::Hardlink,
+            "symlink" => OpKind::Symlink,
+            x => return Err(anyhow!("unknown kind: {x:?}")),
+        };
+        Ok(PlanOp {
+            kind,
+            src: string("src")?.into(),
+            dst: string("dst")?.into(),
+            is_dir: op.get("dir").and_then(Value::as_bool).unwrap_or_default(),
+            size: int("size").map(|s| s as u64),
+            mtime: int("mtime"),
+        })
+    }
+
+    /// Check that the source is unchanged and the target is free, returning both entries.
+    fn check(&self, sources: &HashSet<&PathBuf>) -> Result<(Entry, Entry)> {
+        let entry = Entry::try_from(self.src.clone()).map_err(|_| anyhow!("source not found"))?;
+        if entry.is_dir() != self.is_dir {
+            return Err(anyhow!("source changed type"));
+        }
+        if !self.is_dir && state(&entry)? != (self.size, self.mtime) {
+            return Err(anyhow!("source changed"));
+        }
+        let target = match self.dst.try_exists()? {
+            false => Entry::try_new(&self.dst, self.is_dir)?,
+            true => {
+                let target = Entry::try_from(self.dst.clone()).map_err(|(_, err)| err)?;
+                if !sources.contains(&self.dst) && !ops::is_case_only(&entry, &target) {
+                    return Err(anyhow!("target already exists"));
+                }
+                target
+            }
+        };
+        Ok((entry, target))
+    }
+}
+
+impl ReadyOp {
+    /// Apply the operations, creating the target directories first; it returns the ones that failed.
+    pub fn apply_all(mut ops: Vec<ReadyOp>, transfer: &Transfer) -> Vec<ReadyOp> {
+        ops.iter()
+            .filter_map(|op| op.target.parent())
+            .collect::<HashSet<_>>()
+            .into_iter()
+            .for_each(|dir| {
+                if let Err(err) = fs::create_dir_all(&dir) {
+                    eprintln!("error: {err}: creating {dir}");
+                }
+            });
+
+        let mut failed = vec![];
+        const KINDS: [OpKind; 6] = [
+            OpKind::Rename,
+            OpKind::Move,
+            OpKind::Copy,
+            OpKind::Reflink,
+            OpKind::Hardlink,
+            OpKind::Symlink,
+        ];
+        for kind in KINDS {
+            let (mut batch, rest) = ops.into_iter().partition(|op| op.kind == kind);
+            ops = rest;
+            match kind {
+                OpKind::Rename => FileOps::rename_move(&mut batch),
+                OpKind::Move => {
+                    FileOps::rename_move(&mut batch);
+                    FileOps::cross_move(&mut batch, transfer);
+                }
+                OpKind::Copy => FileOps::copy(&mut batch, transfer),
+                OpKind::Reflink => FileOps::reflink(&mut batch, transfer),
+                OpKind::Hardlink => FileOps::hard_link(&mut batch),
+                OpKind::Symlink => FileOps::symlink(&mut batch),
+            }
+            failed.extend(batch);
+        }
+        failed
+    }
+}
+
+impl OpKind {
+    fn as_str(self) -> &'static str {
+        match self {
+            OpKind::Rename => "rename",
+            OpKind::Move => "move",
+            OpKind::Copy => "copy",
+            OpKind::Reflink => "reflink",
+            OpKind::Hardlink => "hardlink",
+            OpKind::Symlink => "symlink",
+        }
+    }
+}
+
+impl std::fmt::Display for PlanOp {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} -> {}", self.src.display(), self.dst.display())
+    }
+}
+
+impl SourceEntry for ReadyOp {
+    fn src_entry(&self) -> &Entry {
+        &self.entry
+    }
+}
+
+impl NewEntry for ReadyOp {
+    fn new_entry(&self) -> Entry {
+        self.target.clone()
+    }
+}
+
+/// The size and modification time of a file, to detect changes.
+fn state(entry: &Entry) -> Result<(Option<u64>, Option<i64>)> {
+    let md = entry.metadata()?;
+    let mtime = md.modified()?.duration_since(UNIX_EPOCH)?.as_nanos() as i64;
+    Ok((Some(md.len()), Some(mtime)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{Op, Scratch};
+    use std::fs::File;
+    use std::time::Duration;
+
+    #[test]
+    fn save_load() {
+        let dir = Scratch::new("plan-save-load");
+        fs::write(dir.join("a.txt"), "abc").unwrap();
+        fs::create_dir(dir.join("d")).unwrap();
+        let entry = |name: &str, is_dir| Entry::try_new(dir.join(name), is_dir).unwrap();
+        let ops = [
+            Op(entry("a.txt", false), entry("b.txt", false)),
+            Op(entry("d", true), entry("x/d", true)),
+        ];
+        let path = dir.join("plan.toml");
+        Plan::save(&path, "join", OpKind::Copy, &ops).unwrap();
+
+        let plan = Plan::load(&path).unwrap();
+        assert_eq!(plan.command, "join");
+        let ops = plan
+            .ops
+            .iter()
+            .map(|op| (op.kind, op.src.clone(), op.dst.clone(), op.is_dir, op.size))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            ops,
+            [
+                (
+                    OpKind::Copy,
+                    dir.join("a.txt"),
+                    dir.join("b.txt"),
+                    false,
+                    Some(3)
+                ),
+                (OpKind::Copy, dir.join("d"), dir.join("x/d"), true, None),
+            ]
+        );
+        assert!(plan.ops[0].mtime.is_some());
+    }
+
+    #[test]
+    fn validate() {
+        #[track_caller]
+        fn case(name: &str, change: impl FnOnce(&Path), stale: Option<&str>) {
+            let dir = Scratch::new("plan-validate");
+            ["a.txt", "b.txt"]
+                .iter()
+                .for_each(|f| fs::write(dir.join(f), "abc").unwrap());
+            // a swap, which must not be stale since the targets are sources of each other.
+            let ops = [
+                Op(dir.entry("a.txt"), dir.entry("b.txt")),
+                Op(dir.entry("b.txt"), dir.entry("a.txt")),
+                Op(dir.entry("a.txt"), dir.entry("c.txt")),
+            ];
+            let path = dir.join("plan.toml");
+            Plan::save(&path, "rename", OpKind::Rename, &ops).unwrap();
+
+            change(&dir);
+            let (ready, stales) = Plan::load(&path).unwrap().validate();
+            let stales = stales.iter().map(|(_, r)| r.as_str()).collect::<Vec<_>>();
+            assert_eq!(stales.first().copied(), stale, "{name}");
+            assert_eq!(ready.len() + stales.len(), 3, "{name}");
+        }
+
+        case("ok", |_| {}, None);
+        case(
+            "size",
+            |d| fs::write(d.join("a.txt"), "abcd").unwrap(),
+            Some("source changed"),
+        );
+        case(
+            "mtime",
+            |d| {
+                let file = File::options().write(true).open(d.join("b.txt")).unwrap();
+                file.set_modified(UNIX_EPOCH + Duration::from_secs(1))
+                    .unwrap();
+            },
+            Some("source changed"),
+        );
+        case(
+            "missing",
+            |d| fs::remove_file(d.join("b.txt")).unwrap(),
+            Some("source not found"),
+        );
+        case(
+            "occupied",
+            |d| fs::write(d.join("c.txt"), "x").unwrap(),
+            Some("target already exists"),
+        );
+        case(
+            "type",
+            |d| {
+                fs::remove_file(d.join("a.txt")).unwrap();
+                fs::create_dir(d.join("a.txt")).unwrap();
+            },
+            Some("source changed type"),
+        );
+    }
+}
@@ -0,0 +1,184 @@
+use super::{NewEntry, NewNameMut, SourceEntry, fold_key, is_case_only};
+use crate::entries::Entry;
+use crate::utils::{self, PromptError};
+use anyhow::Result;
+use std::collections::HashSet;
+
+/// Step through each change, letting the user accept, skip, or edit its new name, or accept all
+/// the remaining ones, in this directory or everywhere.
+///
+/// Only the approved changes are kept in the list. Edited names are checked against the other
+/// targets and the existing files, so they can't introduce clashes; `fold` compares them ignoring
+/// case, for case-insensitive file systems.
+pub fn review(
+    medias: &mut Vec<impl SourceEntry + NewEntry + NewNameMut>,
+    fold: bool,
+) -> Result<()> {
+    review_with(medias, fold, |msg| utils::prompt_line(msg))
+}
+
+/// Review the changes with the answers given by `prompt`, which is the user in the terminal.
+fn review_with(
+    medias: &mut Vec<impl SourceEntry + NewEntry + NewNameMut>,
+    fold: bool,
+    mut prompt: impl FnMut(&str) -> Result<String, PromptError>,
+) -> Result<()> {
+    use yansi::Paint;
+    println!();
+    let key = |e: &Entry| fold_key(e.to_str(), fold).into_owned();
+    let mut planned = medias
+        .iter()
+        .map(|m| key(&m.new_entry()))
+        .collect::<HashSet<_>>();
+    let total = medias.len();
+    let (mut all, mut dir) = (false, None);
+    let mut approved = Vec::with_capacity(total);
+    for (i, mut m) in std::mem::take(medias).into_iter().enumerate() {
+        let parent = m.src_entry().parent();
+        if all || dir.is_some() && dir == parent {
+            approved.push(m);
+            continue;
+        }
+        let accept = loop {
+            println!(
+                "[{}/{total}] {} --> {}",
+                i + 1,
+                m.src_entry(),
+                m.new_entry()
+            );
+            let msg = "  [y]es [n]o [e]dit [d]ir [a]ll [q]uit:";
+            match prompt(&msg.paint(yansi::Color::BrightBlue).to_string())?.as_str() {
+                "y" | "yes" => break true,
+                "n" | "no" => {
+                    planned.remove(&key(&m.new_entry())); // its target is free for other edits.
+                    break false;
+                }
+                "e" | "edit" => match prompt("  new name:")? {
+                    name if name.is_empty() || name.contains(['/', '\\']) => {
+                        eprintln!("  invalid name: {name:?}");
+                    }
+                    name => {
+                        let old = key(&m.new_entry());
+                        let prev = std::mem::replace(m.new_name_mut(), name);
+                        let (src, target) = (m.src_entry(), m.new_entry());
+                        let taken = key(&target) != old && planned.contains(&key(&target))
+                            || target != *src && target.exists() && !is_case_only(src, &target);
+                        match taken {
+                            true => {
+                                eprintln!("  name already exists: {}", target.file_name());
+                                *m.new_name_mut() = prev;
+                            }
+                            false => {
+                                planned.remove(&old);
+                                planned.insert(key(&target));
+                            }
+                        }
+                    }
+                },
+                "d" | "dir" => {
+                    dir = parent.clone();
+                    break true;
+                }
+                "a" | "all" => {
+                    all = true;
+                    break true;
+                }
+                "q" | "quit" => return Err(PromptError::Quit.into()),
+                _ => {}
+            }
+        };
+        if accept {
+            approved.push(m);
+        }
+    }
+    println!("\napproved {} of {total} changes", approved.len());
+    *medias = approved;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::Scratch;
+
+    struct Change(Entry, String);
+
+    impl SourceEntry for Change {
+        fn src_entry(&self) -> &Entry {
+            &self.0
+        }
+    }
+
+    impl NewEntry for Change {
+        fn new_entry(&self) -> Entry {
+            self.0.with_file_name(&self.1)
+        }
+    }
+
+    impl NewNameMut for Change {
+        fn new_name_mut(&mut self) -> &mut String {
+            &mut self.1
+        }
+    }
+
+    #[test]
+    fn answers() {
+        #[track_caller]
+        fn case(answers: &[&str], out: Option<&[(&str, &str)]>) {
+            let dir = Scratch::new("review");
+            dir.file("x/a", "")
+                .file("x/b", "")
+                .file("y/c", "")
+                .file("y/d", "");
+            let mut medias = [("x/a", "a1"), ("x/b", "b1"), ("y/c", "c1")]
+                .iter()
+                .map(|&(src, new)| Change(dir.entry(src), new.to_owned()))
+                .collect::<Vec<_>>();
+            let mut answers = answers.iter();
+            let prompt = |_: &str| match answers.next() {
+                Some(a) => Ok(a.to_string()),
+                None => Err(PromptError::Quit), // like the end of input.
+            };
+
+            let res = review_with(&mut medias, false, prompt);
+            let approved = medias
+                .iter()
+                .map(|m| {
+                    let src = m.0.strip_prefix(&*dir).unwrap().to_str().unwrap();
+                    (src, m.1.as_str())
+                })
+                .collect::<Vec<_>>();
+            match out {
+                Some(out) => assert_eq!(approved, out),
+                None => assert!(res.is_err()),
+            }
+        }
+
+        let all = [("x/a", "a1"), ("x/b", "b1"), ("y/c", "c1")];
+        case(&["y", "y", "y"], Some(&all));
+        case(&["a"], Some(&all));
+        case(&["?", "yes", "all"], Some(&all));
+        case(&["y", "n", "y"], Some(&[("x/a", "a1"), ("y/c", "c1")]));
+        case(&["d", "n"], Some(&[("x/a", "a1"), ("x/b", "b1")]));
+        case(&["n", "n", "n"], Some(&[]));
+        case(
+            &["e", "z", "y", "y", "y"],
+            Some(&[("x/a", "z"), ("x/b", "b1"), ("y/c", "c1")]),
+        );
+        // invalid names, and clashes with other targets or existing files, are rejected.
+        case(&["e", "", "e", "q/z", "y", "a"], Some(&all));
+        case(&["e", "b1", "y", "a"], Some(&all));
+        case(&["y", "y", "e", "d", "y"], Some(&all));
+        case(
+            &["y", "y", "e", "c", "y"],
+            Some(&[("x/a", "a1"), ("x/b", "b1"), ("y/c", "c")]),
+        );
+        // a declined change frees its target for the others.
+        case(
+            &["n", "e", "a1", "y", "y"],
+            Some(&[("x/b", "a1"), ("y/c", "c1")]),
+        );
+        case(&["y", "q"], None);
+        case(&["y"], None);
+    }
+}
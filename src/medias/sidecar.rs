@@ -0,0 +1,328 @@
+use super::{NewEntry, SourceEntry, is_case_only};
+use crate::entries::Entry;
+use anyhow::{Context, Result};
+use clap::Args;
+use regex::Regex;
+use std::collections::{HashMap, HashSet};
+use std::{fs, iter};
+
+/// The companion files that follow their primary files, like subtitles, metadata, and posters.
+#[derive(Debug, Args)]
+pub struct Sidecars {
+    /// Move sidecar files like "movie.en.srt" or "movie-poster.jpg" together with their primary files.
+    #[arg(long, help_heading = Some("Sidecars"))]
+    sidecars: bool,
+    /// The sidecar patterns, which follow the primary stems; use * as a wildcard.
+    #[arg(long = "sidecar", value_name = "PATTERN", value_delimiter = ',', default_value = PATTERNS, requires = "sidecars", allow_hyphen_values = true, help_heading = Some("Sidecars"))]
+    patterns: Vec<String>,
+}
+
+/// The default sidecar patterns, for media players like Plex and Kodi, and photo editors.
+const PATTERNS: &str = ".srt,.*.srt,.ass,.*.ass,.ssa,.*.ssa,.sub,.idx,.vtt,.*.vtt,.nfo,.xmp,.*.xmp,\
+    -poster.*,-fanart.*,-thumb.*,-banner.*,-landscape.*,-clearlogo.*";
+
+/// The sidecars found for each primary file, along with their suffixes after the primary stem.
+#[derive(Debug, Default)]
+pub struct Bundles {
+    map: HashMap<Entry, Vec<(Entry, String)>>,
+}
+
+impl Sidecars {
+    /// Find the sidecars of the files in the list, looking on disk, and remove them from the list,
+    /// returning the removed ones too.
+    ///
+    /// Sidecars are found even if they were filtered out. It does nothing if sidecars are disabled.
+    pub fn detach<M: SourceEntry>(&self, medias: &mut Vec<M>) -> Result<(Bundles, Vec<M>)> {
+        if !self.sidecars {
+            return Ok((Bundles::default(), vec![]));
+        }
+        let re = self.regex()?;
+
+        // step: index the primaries by their directory and stem; sidecars themselves can't be primaries.
+        let mut primaries = HashMap::<_, Vec<_>>::new();
+        medias
+            .iter()
+            .map(|m| m.src_entry())
+            .filter(|e| !e.is_dir())
+            .filter(|e| {
+                let (stem, _) = e.filename_parts();
+                !re.is_match(&e.file_name()[stem.len()..])
+            })
+            .for_each(|e| {
+                let key = (e.parent(), e.filename_parts().0.to_owned());
+                primaries.entry(key).or_default().push(e);
+            });
+        primaries.retain(|(dir, stem), es| match es.len() {
+            1 => true,
+            _ => {
+                let dir = dir.as_ref().map(|d| d.to_string()).unwrap_or_default();
+                eprintln!("warning: ambiguous sidecars for {dir}{stem}, skipping");
+                false
+            }
+        });
+
+        // step: find the sidecars beside the primaries, matching the longest stems first.
+        let mut bundles = Bundles::default();
+        let dirs = primaries.keys().map(|(dir, _)| dir).collect::<HashSet<_>>();
+        for dir in dirs {
+            let path = dir
+                .clone()
+                .unwrap_or_else(|| Entry::try_new(".", true).unwrap());
+            let rd = fs::read_dir(&path).with_context(|| format!("reading {path}"))?;
+            for name in rd.flatten().filter(|de| de.path().is_file()) {
+                let Ok(name) = name.file_name().into_string() else {
+                    continue; // non UTF-8 names were already reported by the fetcher.
+                };
+                if name.starts_with('.') {
+                    continue;
+                }
+                let primary = name
+                    .char_indices()
+                    .rev()
+                    .filter(|&(i, c)| i > 0 && matches!(c, '.' | '-' | '_'))
+                    .find_map(|(i, _)| {
+                        let es = primaries.get(&(dir.clone(), name[..i].to_owned()))?;
+                        (es[0].file_name() != name && re.is_match(&name[i..]))
+                            .then(|| (es[0], &name[i..]))
+                    });
+                if let Some((primary, suffix)) = primary {
+                    let sidecar = path.join(&name);
+                    bundles
+                        .map
+                        .entry(primary.clone())
+                        .or_default()
+                        .push((sidecar, suffix.to_owned()));
+                }
+            }
+        }
+
+        // step: the sidecars will follow their primaries, so they must not be processed alone.
+        let sidecars = bundles
+            .map
+            .values()
+            .flatten()
+            .map(|(e, _)| e)
+            .collect::<HashSet<_>>();
+        let (detached, kept) = std::mem::take(medias)
+            .into_iter()
+            .partition(|m| sidecars.contains(m.src_entry()));
+        *medias = kept;
+        Ok((bundles, detached))
+    }
+
+    fn regex(&self) -> Result<Regex> {
+        let patterns = self
+            .patterns
+            .iter()
+            .map(|p| regex::escape(p.trim()).replace(r"\*", ".*"))
+            .collect::<Vec<_>>();
+        let re = format!("(?i)^(?:{})$", patterns.join("|"));
+        Regex::new(&re).with_context(|| format!("invalid sidecar patterns: {:?}", self.patterns))
+    }
+}
+
+impl Bundles {
+    /// Put back the detached sidecars whose primaries are not moving, as ordinary files with their
+    /// own new names, blocking the ones that would clash with another file.
+    ///
+    /// It must be called after the primaries got their final names, before attaching the others; it
+    /// returns the number of blocked files.
+    pub fn restore<M: SourceEntry + NewEntry>(
+        &self,
+        medias: &mut Vec<M>,
+        detached: Vec<M>,
+    ) -> usize {
+        let moving = medias
+            .iter()
+            .filter(|m| *m.src_entry() != m.new_entry())
+            .map(|m| m.src_entry())
+            .collect::<HashSet<_>>();
+        let primaries = self
+            .map
+            .iter()
+            .flat_map(|(p, sidecars)| sidecars.iter().map(move |(e, _)| (e, p)))
+            .collect::<HashMap<_, _>>();
+
+        let mut planned = medias.iter().map(M::new_entry).collect::<HashSet<_>>();
+        let mut restored = vec![];
+        let mut blocked = 0;
+        for m in detached {
+            let (src, target) = (m.src_entry(), m.new_entry());
+            if *src == target || primaries.get(src).is_some_and(|p| moving.contains(p)) {
+                continue; // unchanged, or it will follow its primary.
+            }
+            if planned.contains(&target) || target.exists() && !is_case_only(src, &target) {
+                eprintln!("blocked: sidecar clash: {target}: {src}");
+                blocked += 1;
+                continue;
+            }
+            planned.insert(target);
+            restored.push(m);
+        }
+        medias.extend(restored);
+        blocked
+    }
+
+    /// Attach the sidecars to the list, with new names that follow their primaries, blocking whole
+    /// bundles if any sidecar would clash with another file.
+    ///
+    /// It must be called after the primaries got their final names; it returns the number of
+    /// blocked files.
+    pub fn attach<M: SourceEntry + NewEntry>(
+        &self,
+        medias: &mut Vec<M>,
+        new: impl Fn(&M, Entry, String) -> M,
+    ) -> usize {
+        if self.map.is_empty() {
+            return 0;
+        }
+
+        // files that are moving away don't clash, unless another one is moving there.
+        let mut planned = medias.iter().map(M::new_entry).collect::<HashSet<_>>();
+        let sources = medias
+            .iter()
+            .filter(|m| *m.src_entry() != m.new_entry())
+            .flat_map(|m| {
+                let sidecars = self.map.get(m.src_entry()).into_iter().flatten();
+                iter::once(m.src_entry()).chain(sidecars.map(|(e, _)| e))
+            })
+            .cloned()
+            .collect::<HashSet<_>>();
+
+        let (mut blocked, mut attached) = (0, vec![]);
+        medias.retain(|m| {
+            let (src, target) = (m.src_entry(), m.new_entry());
+            let Some(sidecars) = self.map.get(src).filter(|_| *src != target) else {
+                return true;
+            };
+            let stem = match src.filename_parts().1 {
+                "" => target.file_name(),
+                _ => target.filename_parts().0,
+            };
+            let targets = sidecars
+                .iter()
+                .map(|(e, suffix)| (e, target.with_file_name(format!("{stem}{suffix}"))))
+                .collect::<Vec<_>>();
+            let clash = targets.iter().find(|(e, t)| {
+                let case_only = e.to_str().to_lowercase() == t.to_str().to_lowercase();
+                planned.contains(t) || t.exists() && !sources.contains(t) && !case_only
+            });
+            if let Some((_, t)) = clash {
+                eprintln!("blocked: sidecar clash: {t}: {src}");
+                blocked += 1 + sidecars.len();
+                return false;
+            }
+            targets.into_iter().for_each(|(e, t)| {
+                attached.push(new(m, e.clone(), t.file_name().to_owned()));
+                planned.insert(t);
+            });
+            true
+        });
+        medias.extend(attached);
+        blocked
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{Op, Scratch};
+
+    #[test]
+    fn patterns() {
+        #[track_caller]
+        fn case(patterns: &str, suffix: &str, out: bool) {
+            let sidecars = Sidecars {
+                sidecars: true,
+                patterns: patterns.split(',').map(str::to_owned).collect(),
+            };
+            assert_eq!(sidecars.regex().unwrap().is_match(suffix), out, "{suffix}");
+        }
+
+        case(PATTERNS, ".srt", true);
+        case(PATTERNS, ".en.srt", true);
+        case(PATTERNS, ".pt-BR.forced.SRT", true);
+        case(PATTERNS, ".nfo", true);
+        case(PATTERNS, ".jpg.xmp", true);
+        case(PATTERNS, "-poster.jpg", true);
+        case(PATTERNS, "-fanart.png", true);
+        case(PATTERNS, ".mkv", false);
+        case(PATTERNS, ".jpg", false);
+        case(PATTERNS, "-other.jpg", false);
+        case(PATTERNS, ".srt.bak", false);
+        case(".jpg,-cover.*", ".jpg", true);
+        case(".jpg,-cover.*", "-cover.png", true);
+        case(".jpg,-cover.*", ".srt", false);
+    }
+
+    #[test]
+    fn restore_and_attach() {
+        #[track_caller]
+        fn case(ops: &[(&str, &str)], out: &[(&str, &str)], blocked: usize) {
+            let dir = Scratch::new("sidecars");
+            ops.iter().for_each(|(src, _)| {
+                dir.file(src, "");
+            });
+            dir.file("other.pt.srt", "");
+            let mut medias = ops
+                .iter()
+                .map(|(src, new)| Op(dir.entry(src), dir.entry(new)))
+                .collect::<Vec<_>>();
+            let sidecars = Sidecars {
+                sidecars: true,
+                patterns: PATTERNS.split(',').map(str::to_owned).collect(),
+            };
+
+            let (bundles, detached) = sidecars.detach(&mut medias).unwrap();
+            medias.retain(|m| m.0 != m.1); // only the changes are kept, like the commands do.
+            let mut res = bundles.restore(&mut medias, detached);
+            res += bundles.attach(&mut medias, |_, e, name| {
+                Op(e.clone(), e.with_file_name(name))
+            });
+            assert_eq!(res, blocked);
+            let mut changes = medias
+                .iter()
+                .map(|Op(src, new)| (src.file_name(), new.file_name()))
+                .collect::<Vec<_>>();
+            changes.sort();
+            assert_eq!(changes, out);
+        }
+
+        // the sidecars follow their primaries.
+        case(
+            &[("movie.mkv", "film.mkv"), ("movie.en.srt", "movie.en.srt")],
+            &[("movie.en.srt", "film.en.srt"), ("movie.mkv", "film.mkv")],
+            0,
+        );
+        case(
+            &[("movie.mkv", "film.mkv"), ("movie.en.srt", "movie.pt.srt")],
+            &[("movie.en.srt", "film.en.srt"), ("movie.mkv", "film.mkv")],
+            0,
+        );
+        // or are renamed alone, when their primaries don't move.
+        case(
+            &[("movie.mkv", "movie.mkv"), ("movie.en.srt", "movie.pt.srt")],
+            &[("movie.en.srt", "movie.pt.srt")],
+            0,
+        );
+        case(
+            &[("movie.mkv", "movie.mkv"), ("movie.en.srt", "movie.en.srt")],
+            &[],
+            0,
+        );
+        case(
+            &[("movie.mkv", "movie.mkv"), ("movie.en.srt", "other.pt.srt")],
+            &[],
+            1,
+        );
+        case(
+            &[
+                ("movie.mkv", "movie.mkv"),
+                ("movie.en.srt", "movie.es.srt"),
+                ("movie.fr.srt", "movie.es.srt"),
+            ],
+            &[("movie.en.srt", "movie.es.srt")],
+            1,
+        );
+    }
+}
@@ -0,0 +1,405 @@
+use super::{NewNameMut, SourceEntry, meta};
+use crate::entries::Entry;
+use anyhow::{Context, Result, anyhow};
+use chrono::format::{Item, StrftimeItems};
+use chrono::{DateTime, Local};
+use human_repr::HumanCount;
+use regex::{Captures, Regex};
+use std::cell::OnceCell;
+use std::sync::LazyLock;
+
+/// A template that builds new names from placeholders, like "{taken:%Y-%m-%d} {1} {n:3}".
+#[derive(Debug)]
+pub struct Template {
+    parts: Vec<Part>,
+    re: Option<Regex>,
+    /// Whether this template builds folder paths, where literal slashes separate folders.
+    dirs: bool,
+}
+
+#[derive(Debug, PartialEq)]
+enum Part {
+    Text(String),
+    Field(Field),
+}
+
+/// The placeholders supported in templates, along with their formats.
+#[derive(Debug, PartialEq)]
+enum Field {
+    Stem,
+    Ext,
+    /// The kind of media, like "video" or "audio", from the extension.
+    Kind,
+    /// The canonical name of the collection, i.e. the stem without alias, sequence, or comment.
+    Collection,
+    Parent,
+    /// A counter that restarts in each folder, zero-padded to a width.
+    N(usize),
+    /// The modification time, in a strftime format.
+    Mtime(String),
+    Size,
+    /// A capture group from the match regex, by index or name.
+    Capture(String),
+    /// The date a photo was taken from EXIF, in a strftime format.
+    Taken(String),
+    /// The camera model from EXIF.
+    Model,
+    /// Whether the photo has a GPS location in EXIF.
+    Gps,
+    /// The best date available: taken, embedded in the name, or mtime, in a strftime format.
+    Date(String),
+    Artist,
+    Title,
+    Album,
+    Track,
+}
+
+/// The default date format, used when none is given.
+const DATE_FMT: &str = "%Y-%m-%d";
+
+impl Template {
+    /// Parse a template, along with an optional regex that selects the files and provides captures.
+    ///
+    /// Placeholders are enclosed in braces, and literal braces are escaped as `{{` and `}}`.
+    /// The extension is kept apart from the names, so it is only available in folder templates.
+    pub fn parse(text: &str, re: Option<&str>) -> Result<Template> {
+        if text.contains(['/', '\\']) {
+            return Err(anyhow!("templates can't contain path separators: {text:?}"));
+        }
+        let template = Template::parse_with(text, re, false)?;
+        if template.parts.contains(&Part::Field(Field::Ext)) {
+            return Err(anyhow!(
+                "the extension is always kept, remove {{ext}}: {text:?}"
+            ));
+        }
+        Ok(template)
+    }
+
+    /// Parse a template that builds folder paths, like "{kind}/{mtime:%Y}", where literal slashes
+    /// separate nested folders.
+    pub fn parse_dirs(text: &str, re: Option<&str>) -> Result<Template> {
+        if text.contains('\\') {
+            return Err(anyhow!(
+                "folder templates separate folders with '/': {text:?}"
+            ));
+        }
+        Template::parse_with(text, re, true)
+    }
+
+    fn parse_with(text: &str, re: Option<&str>, dirs: bool) -> Result<Template> {
+        static RE: LazyLock<Regex> =
+            LazyLock::new(|| Regex::new(r"\{\{|\}\}|\{([^{}]*)\}|[{}]").unwrap());
+
+        let re = re
+            .map(|r| {
+                Regex::new(&format!("(?i){r}")).with_context(|| format!("compiling regex: {r:?}"))
+            })
+            .transpose()?;
+
+        let (mut parts, mut literal, mut last) = (vec![], String::new(), 0);
+        for caps in RE.captures_iter(text) {
+            let m = caps.get(0).unwrap();
+            literal.push_str(&text[last..m.start()]);
+            last = m.end();
+            match m.as_str() {
+                "{{" => literal.push('{'),
+                "}}" => literal.push('}'),
+                "{" | "}" => return Err(anyhow!("unbalanced braces in template: {text:?}")),
+                _ => {
+                    if !literal.is_empty() {
+                        parts.push(Part::Text(std::mem::take(&mut literal)));
+                    }
+                    parts.push(Part::Field(Field::parse(&caps[1], re.as_ref())?));
+                }
+            }
+        }
+        literal.push_str(&text[last..]);
+        if !literal.is_empty() {
+            parts.push(Part::Text(literal));
+        }
+        Ok(Template { parts, re, dirs })
+    }
+
+    /// Apply this template to the files of a list of media, consuming the ones that couldn't be
+    /// rendered, e.g. because of missing metadata.
+    ///
+    /// Directories and files that don't match the regex are left untouched.
+    /// It returns the number of entries that were blocked.
+    pub fn apply(&self, medias: &mut Vec<impl SourceEntry + NewNameMut>) -> usize {
+        // the counters follow the order of the files in each folder.
+        medias.sort_unstable_by(|m, n| m.src_entry().cmp(n.src_entry()));
+
+        let total = medias.len();
+        let mut counter = (None, 0);
+        medias.retain_mut(|m| {
+            let name = match self.new_name(m.src_entry(), &mut counter) {
+                None => return true,
+                Some(Ok(name)) => name,
+                Some(Err(err)) => {
+                    eprintln!("blocked: {err}: {}", m.src_entry());
+                    return false;
+                }
+            };
+            *m.new_name_mut() = name;
+            true
+        });
+        total - medias.len()
+    }
+
+    /// Render the folder of an entry, relative to the target, or None if it doesn't match the regex.
+    ///
+    /// An empty folder means the entry goes directly into the target.
+    pub fn folder(&self, entry: &Entry) -> Option<Result<String>> {
+        let (stem, _) = entry.filename_parts();
+        let caps = match &self.re {
+            Some(re) => Some(re.captures(stem)?),
+            None => None,
+        };
+        Some(self.render(entry, 1, caps.as_ref()))
+    }
+
+    /// Render the new name of an entry, or None if it is not selected.
+    fn new_name(
+        &self,
+        entry: &Entry,
+        counter: &mut (Option<Entry>, usize),
+    ) -> Option<Result<String>> {
+        if entry.is_dir() {
+            return None;
+        }
+        let (stem, _) = entry.filename_parts();
+        let caps = match &self.re {
+            Some(re) => Some(re.captures(stem)?),
+            None => None,
+        };
+        let parent = entry.parent();
+        if counter.0 != parent {
+            *counter = (parent, 0);
+        }
+        counter.1 += 1;
+        Some(self.render(entry, counter.1, caps.as_ref()))
+    }
+
+    /// Render this template for an entry, with its counter and captures.
+    fn render(&self, entry: &Entry, n: usize, caps: Option<&Captures>) -> Result<String> {
+        let exif = OnceCell::new();
+        let exif = || exif.get_or_init(|| meta::exif_tags(entry).unwrap_or_default());
+        let tags = OnceCell::new();
+        let tags = || tags.get_or_init(|| meta::audio_tags(entry).unwrap_or_default());
+        let tag = |value: Option<&String>, name: &str| {
+            value.cloned().ok_or_else(|| anyhow!("no ID3 {name}"))
+        };
+
+        let mut name = String::new();
+        for part in &self.parts {
+            let value = match part {
+                Part::Text(text) => {
+                    name.push_str(text);
+                    continue;
+                }
+                Part::Field(Field::Stem) => entry.filename_parts().0.to_owned(),
+                Part::Field(Field::Ext) => entry.filename_parts().1.to_owned(),
+                Part::Field(Field::Kind) => meta::media_kind(entry.filename_parts().1).to_owned(),
+                Part::Field(Field::Collection) => entry.collection_parts().0.to_owned(),
+                Part::Field(Field::Parent) => entry
+                    .parent()
+                    .map(|p| p.file_name().to_owned())
+                    .unwrap_or_default(),
+                Part::Field(Field::N(width)) => format!("{n:0width$}"),
+                Part::Field(Field::Mtime(fmt)) => {
+                    let mtime = entry.metadata()?.modified()?;
+                    DateTime::<Local>::from(mtime).format(fmt).to_string()
+                }
+                Part::Field(Field::Size) => entry.metadata()?.len().human_count_bytes().to_string(),
+                Part::Field(Field::Capture(group)) => {
+                    let caps = caps.unwrap(); // captures are only accepted along with a regex.
+                    let m = match group.parse::<usize>() {
+                        Ok(i) => caps.get(i),
+                        Err(_) => caps.name(group),
+                    };
+                    m.map_or("", |m| m.as_str()).to_owned()
+                }
+                Part::Field(Field::Taken(fmt)) => exif()
+                    .taken
+                    .ok_or_else(|| anyhow!("no EXIF date"))?
+                    .format(fmt)
+                    .to_string(),
+                Part::Field(Field::Model) => exif()
+                    .model
+                    .clone()
+                    .ok_or_else(|| anyhow!("no EXIF model"))?,
+                Part::Field(Field::Gps) => match exif().gps {
+                    true => "gps".to_owned(),
+                    false => String::new(),
+                },
+                Part::Field(Field::Date(fmt)) => meta::media_date(entry)
+                    .ok_or_else(|| anyhow!("no date"))?
+                    .format(fmt)
+                    .to_string(),
+                Part::Field(Field::Artist) => tag(tags().artist.as_ref(), "artist")?,
+                Part::Field(Field::Title) => tag(tags().title.as_ref(), "title")?,
+                Part::Field(Field::Album) => tag(tags().album.as_ref(), "album")?,
+                Part::Field(Field::Track) => {
+                    let track = tags().track.ok_or_else(|| anyhow!("no ID3 track"))?;
+                    format!("{track:02}")
+                }
+            };
+            // values might come from metadata, so they can't introduce path separators.
+            name.push_str(&value.replace(['/', '\\'], "-"));
+        }
+
+        if self.dirs {
+            let folders = name.split('/').map(str::trim).filter(|s| !s.is_empty());
+            let folders = folders.collect::<Vec<_>>();
+            if let Some(f) = folders.iter().find(|&&f| f == "." || f == "..") {
+                return Err(anyhow!("template generated an invalid folder: {f:?}"));
+            }
+            return Ok(folders.join("/"));
+        }
+        match name.trim() {
+            "" => Err(anyhow!("template generated an empty name")),
+            x => Ok(x.to_owned()),
+        }
+    }
+}
+
+impl Field {
+    /// Parse a placeholder, like "n:3", validating its format and captures.
+    fn parse(text: &str, re: Option<&Regex>) -> Result<Field> {
+        let (name, spec) = match text.split_once(':') {
+            Some((name, spec)) => (name.trim(), Some(spec)),
+            None => (text.trim(), None),
+        };
+        let date = |spec: Option<&str>| {
+            let fmt = spec.unwrap_or(DATE_FMT);
+            match StrftimeItems::new(fmt).any(|i| i == Item::Error) {
+                true => Err(anyhow!("invalid date format: {fmt:?}")),
+                false => Ok(fmt.to_owned()),
+            }
+        };
+        let field = match (name, spec) {
+            ("n", _) => Field::N(
+                spec.map(str::parse)
+                    .transpose()
+                    .with_context(|| format!("invalid width: {text:?}"))?
+                    .unwrap_or(1),
+            ),
+            ("mtime", _) => Field::Mtime(date(spec)?),
+            ("taken", _) => Field::Taken(date(spec)?),
+            ("date", _) => Field::Date(date(spec)?),
+            (_, Some(_)) => return Err(anyhow!("placeholder doesn't take a format: {text:?}")),
+            ("stem", None) => Field::Stem,
+            ("ext", None) => Field::Ext,
+            ("kind", None) => Field::Kind,
+            ("collection", None) => Field::Collection,
+            ("parent", None) => Field::Parent,
+            ("size", None) => Field::Size,
+            ("model", None) => Field::Model,
+            ("gps", None) => Field::Gps,
+            ("artist", None) => Field::Artist,
+            ("title", None) => Field::Title,
+            ("album", None) => Field::Album,
+            ("track", None) => Field::Track,
+            (group, None) => match re {
+                Some(re) if group.parse().is_ok_and(|i: usize| i < re.captures_len()) => {
+                    Field::Capture(group.to_owned())
+                }
+                Some(re) if re.capture_names().any(|n| n == Some(group)) => {
+                    Field::Capture(group.to_owned())
+                }
+                _ => return Err(anyhow!("unknown placeholder: {text:?}")),
+            },
+        };
+        Ok(field)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn render() {
+        #[track_caller]
+        fn case(template: &str, re: Option<&str>, path: &str, n: usize, out: &str) {
+            let template = Template::parse(template, re).unwrap();
+            let entry = Entry::try_new(path, false).unwrap();
+            let (stem, _) = entry.filename_parts();
+            let caps = template.re.as_ref().map(|re| re.captures(stem).unwrap());
+            assert_eq!(template.render(&entry, n, caps.as_ref()).unwrap(), out);
+        }
+
+        case("{stem}", None, "/a/foo.jpg", 1, "foo");
+        case("{parent} {n:3}", None, "/trip/IMG_1.jpg", 7, "trip 007");
+        case("{stem}.{n}", None, "/a/foo.jpg", 3, "foo.3");
+        case("{{{n}}}", None, "/a/foo.jpg", 12, "{12}");
+        case(
+            "{2} - {1}",
+            Some(r"(\w+) - (\w+)"),
+            "/a/foo - bar.mp3",
+            1,
+            "bar - foo",
+        );
+        case(
+            "{show} S{s}",
+            Some(r"(?<show>\w+)\.s(?<s>\d+)"),
+            "/a/Lost.S02E01.mkv",
+            1,
+            "Lost S02",
+        );
+        case("x{1}", Some(r"img(_)?\d+"), "/a/IMG1234.jpg", 1, "x");
+        case("  {stem}  ", None, "/a/foo.jpg", 1, "foo");
+        case(
+            "{kind}-{collection}",
+            None,
+            "/a/foo~2 bar.mp4",
+            1,
+            "video-foo",
+        );
+    }
+
+    #[test]
+    fn render_dirs() {
+        #[track_caller]
+        fn case(template: &str, re: Option<&str>, path: &str, out: Option<&str>) {
+            let template = Template::parse_dirs(template, re).unwrap();
+            let entry = Entry::try_new(path, false).unwrap();
+            let folder = template.folder(&entry).transpose().unwrap();
+            assert_eq!(folder.as_deref(), out);
+        }
+
+        case("{kind}/", None, "/a/foo.mp3", Some("audio"));
+        case("{ext}/{kind}", None, "/a/foo.JPG", Some("JPG/image"));
+        case("/ {collection} //x/", None, "/a/foo~3.mkv", Some("foo/x"));
+        case(
+            "{1}",
+            Some(r"^(\w+) - "),
+            "/a/Band - Song.mp3",
+            Some("Band"),
+        );
+        case("{1}", Some(r"^(\w+) - "), "/a/song.mp3", None);
+        case("{1}", Some(r"^(\w+)?-"), "/a/-song.mp3", Some(""));
+    }
+
+    #[test]
+    fn invalid_templates() {
+        #[track_caller]
+        fn case(template: &str, re: Option<&str>) {
+            assert!(Template::parse(template, re).is_err(), "{template}");
+        }
+
+        case("{nope}", None);
+        case("{1}", None);
+        case("{2}", Some("(foo)"));
+        case("{name}", Some("(?<other>foo)"));
+        case("{stem:3}", None);
+        case("{n:x}", None);
+        case("{mtime:%Q}", None);
+        case("{stem", None);
+        case("stem}", None);
+        case("{parent}/{stem}", None);
+        case("{stem}.{ext}", None);
+        case("{1}", Some("(foo"));
+        assert!(Template::parse_dirs("{kind}\\{ext}", None).is_err());
+    }
+}
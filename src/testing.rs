@@ -0,0 +1,88 @@
+use crate::entries::Entry;
+use crate::medias::{NewEntry, SourceEntry};
+use std::fs;
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// A fresh scratch directory for tests that need real files, which is removed when dropped.
+pub struct Scratch(PathBuf);
+
+impl Scratch {
+    pub fn new(name: &str) -> Scratch {
+        static NEXT: AtomicUsize = AtomicUsize::new(0);
+        let n = NEXT.fetch_add(1, Ordering::Relaxed);
+        let dir = std::env::temp_dir().join(format!("refine-{name}-{}-{n}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        Scratch(dir.canonicalize().unwrap())
+    }
+
+    /// Create a file with the given contents, along with its parent directories.
+    pub fn file(&self, name: &str, contents: &str) -> &Self {
+        let path = self.join(name);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, contents).unwrap();
+        self
+    }
+
+    /// Get an entry in the scratch directory, which checks its directory flag if it exists.
+    pub fn entry(&self, name: &str) -> Entry {
+        let path = self.join(name);
+        let is_dir = path.is_dir();
+        Entry::try_new(path, is_dir).unwrap()
+    }
+
+    /// The names of all files in the scratch directory, recursively and sorted.
+    pub fn files(&self) -> Vec<String> {
+        fn walk(dir: &Path, base: &Path, acc: &mut Vec<String>) {
+            for de in fs::read_dir(dir).unwrap() {
+                let path = de.unwrap().path();
+                match path.is_dir() {
+                    true => walk(&path, base, acc),
+                    false => acc.push(
+                        path.strip_prefix(base)
+                            .unwrap()
+                            .to_str()
+                            .unwrap()
+                            .to_owned(),
+                    ),
+                }
+            }
+        }
+
+        let mut acc = vec![];
+        walk(&self.0, &self.0, &mut acc);
+        acc.sort();
+        acc
+    }
+}
+
+impl Deref for Scratch {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for Scratch {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+/// A file operation from a source to a target entry.
+pub struct Op(pub Entry, pub Entry);
+
+impl SourceEntry for Op {
+    fn src_entry(&self) -> &Entry {
+        &self.0
+    }
+}
+
+impl NewEntry for Op {
+    fn new_entry(&self) -> Entry {
+        self.1.clone()
+    }
+}
@@ -1,12 +1,15 @@
 mod natural;
 mod running;
 
-use anyhow::{Result, anyhow};
+use anyhow::{Context, Result, anyhow};
 pub use natural::*;
 pub use running::*;
 use std::collections::HashSet;
+use std::env;
 use std::error::Error;
 use std::io::{Write, stdin, stdout};
+use std::path::Path;
+use std::process::Command;
 use std::str::FromStr;
 use std::sync::{LazyLock, Mutex, mpsc};
 use std::thread;
@@ -35,28 +38,33 @@ impl From<PromptError> for anyhow::Error {
 
 /// Prompt the user for confirmation.
 pub fn prompt_yes_no(msg: impl Into<Box<str>>) -> Result<(), PromptError> {
+    let msg = msg.into();
+    loop {
+        match prompt_line(format!("{msg} [y|n|q]:"))?.as_str() {
+            "y" | "yes" => break Ok(()),
+            "n" | "no" => break Err(PromptError::No),
+            "q" | "quit" => break Err(PromptError::Quit),
+            _ => {}
+        }
+    }
+}
+
+/// Prompt the user for a line of input, which is returned trimmed; the end of input means quit.
+pub fn prompt_line(msg: impl Into<Box<str>>) -> Result<String, PromptError> {
     let (tx, rx) = mpsc::channel();
     let msg = msg.into(); // I need ownership of an immutable message here.
-    let f = move |input: &mut String| {
-        aborted()?;
-        print!("{msg} [y|n|q]: ");
-        stdout().flush()?;
-        input.clear();
-        stdin().read_line(input)?;
-        Ok::<_, anyhow::Error>(())
-    };
     thread::spawn(move || {
-        let mut input = String::new();
-        let res = loop {
-            match (f(&mut input), input.trim()) {
-                (Err(err), _) => break Err(err.into()),
-                (Ok(()), "y" | "yes") => break Ok(()),
-                (Ok(()), "n" | "no") => break Err(PromptError::No),
-                (Ok(()), "q" | "quit") => break Err(PromptError::Quit),
-                _ => {}
+        let f = || {
+            aborted()?;
+            print!("{msg} ");
+            stdout().flush()?;
+            let mut input = String::new();
+            match stdin().read_line(&mut input)? {
+                0 => Err(anyhow!("end of input")),
+                _ => Ok(input.trim().to_owned()),
             }
         };
-        let _ = tx.send(res);
+        let _ = tx.send(f().map_err(PromptError::from));
     });
 
     loop {
@@ -67,6 +75,28 @@ pub fn prompt_yes_no(msg: impl Into<Box<str>>) -> Result<(), PromptError> {
     }
 }
 
+/// Open a file in the user's editor, and wait for it to finish successfully.
+///
+/// The editor comes from $VISUAL or $EDITOR, which may include arguments, like "code -w".
+pub fn launch_editor(path: &Path) -> Result<()> {
+    let editor = env::var("VISUAL")
+        .or_else(|_| env::var("EDITOR"))
+        .unwrap_or_else(|_| "vi".to_owned());
+    let mut args = editor.split_whitespace();
+    let program = args
+        .next()
+        .ok_or_else(|| anyhow!("invalid editor: {editor:?}"))?;
+    let status = Command::new(program)
+        .args(args)
+        .arg(path)
+        .status()
+        .with_context(|| format!("running editor: {editor:?}"))?;
+    match status.success() {
+        true => Ok(()),
+        false => Err(anyhow!("editor exited with {status}")),
+    }
+}
+
 /// Intern a string, to prevent duplicates and redundant allocations.
 pub fn intern(text: &str) -> &'static str {
     static CACHE: LazyLock<Mutex<HashSet<&'static str>>> = LazyLock::new(Default::default);
@@ -82,6 +112,14 @@ pub fn intern(text: &str) -> &'static str {
     }
 }
 
+/// Calculate how similar two strings are, from 0.0 to 1.0, with the best of the normalized
+/// Levenshtein and Sørensen-Dice scores.
+pub fn similarity(a: &str, b: &str) -> f64 {
+    let lev = strsim::normalized_levenshtein(a, b);
+    let dice = strsim::sorensen_dice(a, b);
+    lev.max(dice)
+}
+
 /// Parse a key-value pair from a string, for use in clap.
 pub fn parse_key_value<K, V>(s: &str) -> Result<(K, V)>
 where
@@ -93,3 +131,29 @@ where
         .ok_or_else(|| anyhow!("missing =value in: {s:?}"))?;
     Ok((s[..pos].parse()?, s[pos + 1..].parse()?))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn similarities() {
+        #[track_caller]
+        fn case(a: &str, b: &str, out: f64) {
+            assert!(
+                (similarity(a, b) - out).abs() < 1e-9,
+                "{}",
+                similarity(a, b)
+            );
+            assert_eq!(similarity(a, b), similarity(b, a));
+        }
+
+        case("foobar", "foobar", 1.);
+        case("", "", 1.);
+        case("abc", "xyz", 0.);
+        case("fo0bar", "foobar", 5. / 6.); // levenshtein wins.
+        case("f00bar", "foobar", 2. / 3.);
+        case("night", "nacht", 0.6);
+        case("barfoo", "foobar", 0.8); // dice wins.
+    }
+}